@@ -0,0 +1,791 @@
+mod store;
+
+use std::{future::Future, pin::Pin, sync::atomic::Ordering, time::Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    telemetry,
+    transport::{HttpRequest, HttpResponse},
+    MetricLabels, RetryPolicy, Shopify, ShopifyAPIError,
+};
+pub use store::{FileTokenStore, MemoryTokenStore};
+
+pub type TokenStoreFuture<'a, T = ()> =
+    Pin<Box<dyn Future<Output = Result<T, ShopifyAPIError>> + Send + 'a>>;
+
+/// Persists token data between process restarts.
+///
+/// Tokens are keyed by session id: the shop domain for offline tokens (see
+/// [`offline_session_id`]) and `{shop}_{user_id}` for online tokens (see
+/// [`online_session_id`]).
+pub trait TokenStore: Send + Sync {
+    fn save_token<'a>(&'a self, session_id: &'a str, token: TokenData) -> TokenStoreFuture<'a>;
+
+    fn load_token<'a>(&'a self, _session_id: &'a str) -> TokenStoreFuture<'a, Option<TokenData>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn delete_token<'a>(&'a self, _session_id: &'a str) -> TokenStoreFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Session id of a shop's offline token: its normalized shop domain.
+pub fn offline_session_id(shop: &str) -> String {
+    crate::normalize_shop_domain(shop)
+}
+
+/// Session id of a staff member's online token on a shop.
+pub fn online_session_id(shop: &str, user_id: u64) -> String {
+    format!("{}_{user_id}", crate::normalize_shop_domain(shop))
+}
+
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TokenData {
+    pub access_token: String,
+    pub scope: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub refresh_token: Option<String>,
+    pub refresh_token_expires_at: Option<DateTime<Utc>>,
+    /// The staff member an online (per-user) token was issued for.
+    #[serde(default)]
+    pub associated_user: Option<AssociatedUser>,
+    #[serde(default)]
+    pub associated_user_scope: Option<String>,
+    /// The OpenID Connect ID token of a customer login, sent as the hint when
+    /// logging the customer out.
+    #[serde(default)]
+    pub id_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AssociatedUser {
+    pub id: u64,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub account_owner: Option<bool>,
+    pub locale: Option<String>,
+    pub collaborator: Option<bool>,
+}
+
+impl std::fmt::Debug for TokenData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenData")
+            .field("access_token", &"<redacted>")
+            .field("scope", &self.scope)
+            .field("expires_at", &self.expires_at)
+            .field(
+                "refresh_token",
+                &self.refresh_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("refresh_token_expires_at", &self.refresh_token_expires_at)
+            .field(
+                "associated_user",
+                &self.associated_user.as_ref().map(|user| user.id),
+            )
+            .field("associated_user_scope", &self.associated_user_scope)
+            .field("id_token", &self.id_token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl TokenData {
+    pub fn never_expiring(access_token: impl Into<String>) -> Self {
+        Self {
+            access_token: access_token.into(),
+            scope: None,
+            expires_at: None,
+            refresh_token: None,
+            refresh_token_expires_at: None,
+            associated_user: None,
+            associated_user_scope: None,
+            id_token: None,
+        }
+    }
+
+    /// The session id this token is stored under for `shop`: the online
+    /// session id when it has an associated user, the offline one otherwise.
+    pub fn session_id(&self, shop: &str) -> String {
+        match &self.associated_user {
+            Some(user) => online_session_id(shop, user.id),
+            None => offline_session_id(shop),
+        }
+    }
+
+    pub fn expires_within(&self, leeway: chrono::Duration) -> bool {
+        self.expires_at
+            .map(|expires_at| expires_at <= Utc::now() + leeway)
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub enum ShopifyAuth {
+    AccessToken(String),
+    ClientCredentials {
+        client_id: String,
+        client_secret: String,
+        current_token: Option<TokenData>,
+    },
+    ExpiringOfflineToken {
+        client_id: String,
+        client_secret: String,
+        token: TokenData,
+    },
+    /// A per-user token. It expires with the staff member's session and
+    /// cannot be refreshed; request a new one through OAuth or token exchange.
+    OnlineToken(TokenData),
+}
+
+impl std::fmt::Debug for ShopifyAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShopifyAuth::AccessToken(_) => {
+                f.debug_tuple("AccessToken").field(&"<redacted>").finish()
+            }
+            ShopifyAuth::ClientCredentials { current_token, .. } => f
+                .debug_struct("ClientCredentials")
+                .field("client_id", &"<redacted>")
+                .field("client_secret", &"<redacted>")
+                .field("current_token", current_token)
+                .finish(),
+            ShopifyAuth::ExpiringOfflineToken { token, .. } => f
+                .debug_struct("ExpiringOfflineToken")
+                .field("client_id", &"<redacted>")
+                .field("client_secret", &"<redacted>")
+                .field("token", token)
+                .finish(),
+            ShopifyAuth::OnlineToken(token) => f.debug_tuple("OnlineToken").field(token).finish(),
+        }
+    }
+}
+
+impl ShopifyAuth {
+    pub fn client_credentials(
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self::ClientCredentials {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            current_token: None,
+        }
+    }
+
+    pub fn expiring_offline_token(
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        token: TokenData,
+    ) -> Self {
+        Self::ExpiringOfflineToken {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            token,
+        }
+    }
+
+    /// Replaces the current token with one loaded from a [`TokenStore`].
+    pub fn with_token(self, token: TokenData) -> Self {
+        match self {
+            Self::AccessToken(_) => Self::AccessToken(token.access_token),
+            Self::ClientCredentials {
+                client_id,
+                client_secret,
+                ..
+            } => Self::ClientCredentials {
+                client_id,
+                client_secret,
+                current_token: Some(token),
+            },
+            Self::ExpiringOfflineToken {
+                client_id,
+                client_secret,
+                ..
+            } => Self::ExpiringOfflineToken {
+                client_id,
+                client_secret,
+                token,
+            },
+            Self::OnlineToken(_) => Self::OnlineToken(token),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TokenResponse {
+    access_token: String,
+    scope: Option<String>,
+    expires_in: Option<i64>,
+    refresh_token: Option<String>,
+    refresh_token_expires_in: Option<i64>,
+    associated_user: Option<AssociatedUser>,
+    associated_user_scope: Option<String>,
+    id_token: Option<String>,
+}
+
+impl TokenResponse {
+    pub(crate) fn into_token_data(self) -> TokenData {
+        TokenData {
+            access_token: self.access_token,
+            scope: self.scope,
+            expires_at: self
+                .expires_in
+                .map(|seconds| Utc::now() + chrono::Duration::seconds(seconds)),
+            refresh_token: self.refresh_token,
+            refresh_token_expires_at: self
+                .refresh_token_expires_in
+                .map(|seconds| Utc::now() + chrono::Duration::seconds(seconds)),
+            associated_user: self.associated_user,
+            associated_user_scope: self.associated_user_scope,
+            id_token: self.id_token,
+        }
+    }
+}
+
+impl Shopify {
+    /// Returns a usable access token, refreshing it first when needed.
+    ///
+    /// Refreshes are single-flight across clones of this client: concurrent
+    /// callers wait for the refresh in progress and reuse its token, so a
+    /// rotated refresh token is only ever spent once.
+    pub async fn access_token(&self) -> Result<String, ShopifyAPIError> {
+        if self.load_token_from_store && !self.token_loaded.load(Ordering::Acquire) {
+            let _refresh = self.refresh_lock.lock().await;
+            // Another caller may have loaded, and even refreshed, the token
+            // while this one waited.
+            if !self.token_loaded.load(Ordering::Acquire) {
+                self.load_stored_token().await?;
+            }
+        }
+
+        if let Some(token) = self.usable_token(&self.current_auth()?) {
+            return Ok(token);
+        }
+
+        let _refresh = self.refresh_lock.lock().await;
+        // Another caller may have refreshed the token while this one waited.
+        let auth = self.current_auth()?;
+        if let Some(token) = self.usable_token(&auth) {
+            return Ok(token);
+        }
+
+        self.refresh_auth(auth).await
+    }
+
+    /// Sends a request built with the current access token. When Shopify
+    /// answers `401 Unauthorized`, refreshes the token regardless of its
+    /// expiry and replays the request once.
+    ///
+    /// Static and online tokens cannot be refreshed, so a 401 returns
+    /// [`ShopifyAPIError::InvalidAccessToken`].
+    pub(crate) async fn send_authorized(
+        &self,
+        request: HttpRequest,
+    ) -> Result<HttpResponse, ShopifyAPIError> {
+        let token = self.access_token().await?;
+        let response = self
+            .send(with_access_token(request.clone(), &token)?)
+            .await?;
+        if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        log::debug!("shopify rejected the access token, refreshing it");
+        let token = self.refresh_rejected_token(&token).await?;
+        let response = self.send(with_access_token(request, &token)?).await?;
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(ShopifyAPIError::InvalidAccessToken);
+        }
+        Ok(response)
+    }
+
+    async fn refresh_rejected_token(&self, rejected: &str) -> Result<String, ShopifyAPIError> {
+        let _refresh = self.refresh_lock.lock().await;
+        let auth = self.current_auth()?;
+        if matches!(
+            auth,
+            ShopifyAuth::AccessToken(_) | ShopifyAuth::OnlineToken(_)
+        ) {
+            return Err(ShopifyAPIError::InvalidAccessToken);
+        }
+        // Another caller may already have replaced the rejected token.
+        if let Some(token) = self.usable_token(&auth).filter(|token| token != rejected) {
+            return Ok(token);
+        }
+
+        self.refresh_auth(auth).await
+    }
+
+    fn current_auth(&self) -> Result<ShopifyAuth, ShopifyAPIError> {
+        Ok(self
+            .auth
+            .lock()
+            .map_err(|_| ShopifyAPIError::Authentication("auth lock poisoned".to_string()))?
+            .clone())
+    }
+
+    fn usable_token(&self, auth: &ShopifyAuth) -> Option<String> {
+        let token = match auth {
+            ShopifyAuth::AccessToken(token) => return Some(token.clone()),
+            ShopifyAuth::ClientCredentials { current_token, .. } => current_token.as_ref()?,
+            ShopifyAuth::ExpiringOfflineToken { token, .. } => token,
+            ShopifyAuth::OnlineToken(token) if !token.expires_within(chrono::Duration::zero()) => {
+                return Some(token.access_token.clone())
+            }
+            ShopifyAuth::OnlineToken(_) => return None,
+        };
+
+        (!token.expires_within(self.token_refresh_leeway)).then(|| token.access_token.clone())
+    }
+
+    async fn refresh_auth(&self, auth: ShopifyAuth) -> Result<String, ShopifyAPIError> {
+        let grant = match &auth {
+            ShopifyAuth::ClientCredentials { .. } => "client_credentials",
+            ShopifyAuth::ExpiringOfflineToken { .. } => "refresh_token",
+            ShopifyAuth::AccessToken(_) | ShopifyAuth::OnlineToken(_) => "none",
+        };
+        let span = telemetry::Span::token_refresh(&self.shop_domain, grant);
+        let started_at = Instant::now();
+        let result = span.instrument(self.refresh_auth_with_grant(auth)).await;
+        span.record_result(&result, started_at);
+
+        if let Some(metrics) = self.metrics() {
+            let labels = MetricLabels {
+                shop: &self.shop_domain,
+                operation: grant,
+            };
+            metrics.token_refresh(&labels, started_at.elapsed(), result.as_ref().err());
+        }
+        result
+    }
+
+    async fn refresh_auth_with_grant(&self, auth: ShopifyAuth) -> Result<String, ShopifyAPIError> {
+        match auth {
+            ShopifyAuth::AccessToken(token) => Ok(token),
+            ShopifyAuth::OnlineToken(_) => Err(ShopifyAPIError::Authentication(
+                "online access token has expired".to_string(),
+            )),
+            ShopifyAuth::ClientCredentials {
+                client_id,
+                client_secret,
+                ..
+            } => {
+                let token = self
+                    .request_client_credentials_token(&client_id, &client_secret)
+                    .await?;
+                self.persist_and_replace_auth(
+                    ShopifyAuth::ClientCredentials {
+                        client_id,
+                        client_secret,
+                        current_token: Some(token.clone()),
+                    },
+                    token.clone(),
+                )
+                .await?;
+                Ok(token.access_token)
+            }
+            ShopifyAuth::ExpiringOfflineToken {
+                client_id,
+                client_secret,
+                token,
+            } => {
+                let refreshed = match self
+                    .refresh_offline_token(&client_id, &client_secret, &token)
+                    .await
+                {
+                    Ok(refreshed) => refreshed,
+                    Err(err) => {
+                        // Another process sharing the store may have rotated the
+                        // refresh token; pick up its token instead of failing.
+                        let stored = match &self.token_store {
+                            Some(store) => store.load_token(self.session_id()).await?,
+                            None => None,
+                        };
+                        let Some(stored) =
+                            stored.filter(|stored| stored.refresh_token != token.refresh_token)
+                        else {
+                            return Err(err);
+                        };
+
+                        if stored.expires_within(self.token_refresh_leeway) {
+                            self.refresh_offline_token(&client_id, &client_secret, &stored)
+                                .await?
+                        } else {
+                            let access_token = stored.access_token.clone();
+                            self.replace_auth(ShopifyAuth::ExpiringOfflineToken {
+                                client_id,
+                                client_secret,
+                                token: stored,
+                            })?;
+                            return Ok(access_token);
+                        }
+                    }
+                };
+
+                self.persist_and_replace_auth(
+                    ShopifyAuth::ExpiringOfflineToken {
+                        client_id,
+                        client_secret,
+                        token: refreshed.clone(),
+                    },
+                    refreshed.clone(),
+                )
+                .await?;
+                Ok(refreshed.access_token)
+            }
+        }
+    }
+
+    async fn refresh_offline_token(
+        &self,
+        client_id: &str,
+        client_secret: &str,
+        token: &TokenData,
+    ) -> Result<TokenData, ShopifyAPIError> {
+        let refresh_token = token.refresh_token.as_deref().ok_or_else(|| {
+            ShopifyAPIError::Authentication(
+                "expiring offline token is missing refresh_token".to_string(),
+            )
+        })?;
+        if let Some(expires_at) = token.refresh_token_expires_at {
+            if expires_at <= Utc::now() {
+                return Err(ShopifyAPIError::Authentication(format!(
+                    "refresh token expired at {expires_at}; the shop must reauthorize the app"
+                )));
+            }
+        }
+
+        self.refresh_token_with_credentials(client_id, client_secret, refresh_token)
+            .await
+    }
+
+    /// Replaces the current token with the one saved under
+    /// [`Shopify::session_id`], if the store has one. Returns whether a token
+    /// was loaded.
+    ///
+    /// Called on the first [`Shopify::access_token`] when
+    /// [`ShopifyConfig::load_token_from_store`](crate::ShopifyConfig) is set.
+    /// Waits for a refresh in progress, so a token it already rotated is not
+    /// replaced with the stale one.
+    pub async fn load_token_from_store(&self) -> Result<bool, ShopifyAPIError> {
+        let _refresh = self.refresh_lock.lock().await;
+        self.load_stored_token().await
+    }
+
+    /// [`Shopify::load_token_from_store`] for callers holding `refresh_lock`.
+    async fn load_stored_token(&self) -> Result<bool, ShopifyAPIError> {
+        let Some(store) = &self.token_store else {
+            return Ok(false);
+        };
+
+        let loaded = match store.load_token(self.session_id()).await? {
+            Some(token) => {
+                self.replace_auth(self.current_auth()?.with_token(token))?;
+                true
+            }
+            None => false,
+        };
+        // Set only once the auth is replaced, so callers that skip loading
+        // never see the initial auth.
+        self.token_loaded.store(true, Ordering::Release);
+        Ok(loaded)
+    }
+
+    /// Deletes the token saved under [`Shopify::session_id`], for example
+    /// after the app was uninstalled.
+    pub async fn delete_stored_token(&self) -> Result<(), ShopifyAPIError> {
+        match &self.token_store {
+            Some(store) => store.delete_token(self.session_id()).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn persist_and_replace_auth(
+        &self,
+        auth: ShopifyAuth,
+        token: TokenData,
+    ) -> Result<(), ShopifyAPIError> {
+        self.replace_auth(auth)?;
+        if let Some(store) = &self.token_store {
+            store.save_token(self.session_id(), token).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn request_client_credentials_token(
+        &self,
+        client_id: &str,
+        client_secret: &str,
+    ) -> Result<TokenData, ShopifyAPIError> {
+        request_token(
+            |request| self.send(request),
+            self.retry_policy(),
+            self.token_url(),
+            &[
+                ("grant_type", "client_credentials"),
+                ("client_id", client_id),
+                ("client_secret", client_secret),
+            ],
+        )
+        .await
+    }
+
+    pub async fn refresh_token_with_credentials(
+        &self,
+        client_id: &str,
+        client_secret: &str,
+        refresh_token: &str,
+    ) -> Result<TokenData, ShopifyAPIError> {
+        // Refresh tokens rotate on use, so only retry when Shopify did not
+        // process the request.
+        request_token(
+            |request| self.send(request),
+            &self.retry_policy().unprocessed_only(),
+            self.token_url(),
+            &[
+                ("grant_type", "refresh_token"),
+                ("client_id", client_id),
+                ("client_secret", client_secret),
+                ("refresh_token", refresh_token),
+            ],
+        )
+        .await
+    }
+}
+
+fn with_access_token(request: HttpRequest, token: &str) -> Result<HttpRequest, ShopifyAPIError> {
+    let mut value = reqwest::header::HeaderValue::from_str(token)?;
+    value.set_sensitive(true);
+    Ok(request.header(
+        reqwest::header::HeaderName::from_static("x-shopify-access-token"),
+        value,
+    ))
+}
+
+/// Posts a form to a shop's `/admin/oauth/access_token` endpoint.
+pub(crate) async fn request_token<F, Fut>(
+    send: F,
+    retry: &RetryPolicy,
+    token_url: &str,
+    form: &[(&str, &str)],
+) -> Result<TokenData, ShopifyAPIError>
+where
+    F: Fn(HttpRequest) -> Fut,
+    Fut: Future<Output = Result<HttpResponse, ShopifyAPIError>>,
+{
+    let response = retry
+        .run(|| async {
+            let response = send(HttpRequest::post(token_url).form(form)).await?;
+            telemetry::Span::current().record_status(response.status());
+            crate::utils::error_for_status(response)
+                .await?
+                .json::<TokenResponse>()
+                .await
+        })
+        .await?;
+
+    Ok(response.into_token_data())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_expiration_uses_leeway() {
+        let token = TokenData {
+            access_token: "token".to_string(),
+            scope: None,
+            expires_at: Some(Utc::now() + chrono::Duration::minutes(1)),
+            refresh_token: None,
+            refresh_token_expires_at: None,
+            associated_user: None,
+            associated_user_scope: None,
+            id_token: None,
+        };
+
+        assert!(token.expires_within(chrono::Duration::minutes(5)));
+        assert!(!token.expires_within(chrono::Duration::seconds(1)));
+    }
+
+    #[test]
+    fn online_token_response_keeps_associated_user() {
+        let response: TokenResponse = serde_json::from_value(serde_json::json!({
+            "access_token": "token",
+            "scope": "read_products",
+            "expires_in": 86399,
+            "associated_user_scope": "read_products",
+            "associated_user": {"id": 42, "email": "staff@example.com", "account_owner": true}
+        }))
+        .unwrap();
+
+        let token = response.into_token_data();
+
+        assert_eq!(token.associated_user.as_ref().map(|user| user.id), Some(42));
+        assert!(token.expires_within(chrono::Duration::days(1)));
+        assert!(!format!("{token:?}").contains("staff@example.com"));
+    }
+
+    struct SingleTokenStore(std::sync::Mutex<Option<(String, TokenData)>>);
+
+    impl TokenStore for SingleTokenStore {
+        fn save_token<'a>(&'a self, session_id: &'a str, token: TokenData) -> TokenStoreFuture<'a> {
+            *self.0.lock().unwrap() = Some((session_id.to_string(), token));
+            Box::pin(async { Ok(()) })
+        }
+
+        fn load_token<'a>(
+            &'a self,
+            session_id: &'a str,
+        ) -> TokenStoreFuture<'a, Option<TokenData>> {
+            let token = self
+                .0
+                .lock()
+                .unwrap()
+                .clone()
+                .filter(|(id, _)| id == session_id)
+                .map(|(_, token)| token);
+            Box::pin(async { Ok(token) })
+        }
+
+        fn delete_token<'a>(&'a self, _session_id: &'a str) -> TokenStoreFuture<'a> {
+            *self.0.lock().unwrap() = None;
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn hydrates_auth_from_store_by_session_id() {
+        let session_id = online_session_id("my-shop", 42);
+        let store = std::sync::Arc::new(SingleTokenStore(std::sync::Mutex::new(Some((
+            session_id.clone(),
+            TokenData::never_expiring("stored"),
+        )))));
+        let shopify = Shopify::new(
+            "my-shop",
+            ShopifyAuth::OnlineToken(TokenData::never_expiring("initial")),
+            crate::ShopifyConfig {
+                token_store: Some(store.clone()),
+                session_id: Some(session_id),
+                load_token_from_store: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(shopify.session_id(), "my-shop.myshopify.com_42");
+        assert_eq!(shopify.access_token().await.unwrap(), "stored");
+
+        shopify.delete_stored_token().await.unwrap();
+        assert!(!shopify.load_token_from_store().await.unwrap());
+        assert_eq!(shopify.access_token().await.unwrap(), "stored");
+    }
+
+    /// Counts loads, each taking a while to answer.
+    #[derive(Default)]
+    struct SlowStore(std::sync::atomic::AtomicUsize);
+
+    impl TokenStore for SlowStore {
+        fn save_token<'a>(
+            &'a self,
+            _session_id: &'a str,
+            _token: TokenData,
+        ) -> TokenStoreFuture<'a> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn load_token<'a>(
+            &'a self,
+            _session_id: &'a str,
+        ) -> TokenStoreFuture<'a, Option<TokenData>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                Ok(Some(TokenData::never_expiring("stored")))
+            })
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_first_callers_load_the_stored_token_once() {
+        let store = std::sync::Arc::new(SlowStore::default());
+        let shopify = Shopify::new(
+            "my-shop",
+            ShopifyAuth::AccessToken("initial".to_string()),
+            crate::ShopifyConfig {
+                token_store: Some(store.clone()),
+                load_token_from_store: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let callers = (0..8)
+            .map(|_| {
+                let shopify = shopify.clone();
+                tokio::spawn(async move { shopify.access_token().await.unwrap() })
+            })
+            .collect::<Vec<_>>();
+        for caller in callers {
+            assert_eq!(caller.await.unwrap(), "stored");
+        }
+        assert_eq!(store.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_refresh_token_is_an_authentication_error() {
+        let token = TokenData {
+            expires_at: Some(Utc::now() - chrono::Duration::minutes(1)),
+            refresh_token: Some("refresh".to_string()),
+            refresh_token_expires_at: Some(Utc::now() - chrono::Duration::days(1)),
+            ..TokenData::never_expiring("expired")
+        };
+        let shopify = Shopify::new(
+            "my-shop",
+            ShopifyAuth::expiring_offline_token("id", "secret", token),
+            crate::ShopifyConfig::default(),
+        )
+        .unwrap();
+
+        let err = shopify.access_token().await.unwrap_err();
+        assert!(
+            matches!(&err, ShopifyAPIError::Authentication(message) if message.contains("refresh token expired")),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_static_token_is_reported_as_invalid() {
+        let shopify = Shopify::new(
+            "my-shop",
+            ShopifyAuth::AccessToken("revoked".to_string()),
+            crate::ShopifyConfig::default(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            shopify.refresh_rejected_token("revoked").await,
+            Err(ShopifyAPIError::InvalidAccessToken)
+        ));
+    }
+
+    #[test]
+    fn session_id_depends_on_associated_user() {
+        let mut token = TokenData::never_expiring("token");
+        assert_eq!(token.session_id("my-shop"), "my-shop.myshopify.com");
+
+        token.associated_user = serde_json::from_value(serde_json::json!({"id": 7})).ok();
+        assert_eq!(token.session_id("my-shop"), "my-shop.myshopify.com_7");
+    }
+
+    #[test]
+    fn static_token_never_expires() {
+        let token = TokenData::never_expiring("token");
+
+        assert!(!token.expires_within(chrono::Duration::days(365)));
+    }
+}
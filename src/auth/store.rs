@@ -0,0 +1,391 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, Mutex, Weak,
+    },
+};
+
+use super::{TokenData, TokenStore, TokenStoreFuture};
+use crate::ShopifyAPIError;
+
+/// A [`TokenStore`] that keeps tokens in memory, for tests and
+/// single-process apps.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    tokens: Mutex<BTreeMap<String, TokenData>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tokens(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, BTreeMap<String, TokenData>>, ShopifyAPIError> {
+        self.tokens
+            .lock()
+            .map_err(|_| ShopifyAPIError::Other("token store lock poisoned".to_string()))
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn save_token<'a>(&'a self, session_id: &'a str, token: TokenData) -> TokenStoreFuture<'a> {
+        Box::pin(async move {
+            self.tokens()?.insert(session_id.to_string(), token);
+            Ok(())
+        })
+    }
+
+    fn load_token<'a>(&'a self, session_id: &'a str) -> TokenStoreFuture<'a, Option<TokenData>> {
+        Box::pin(async move { Ok(self.tokens()?.get(session_id).cloned()) })
+    }
+
+    fn delete_token<'a>(&'a self, session_id: &'a str) -> TokenStoreFuture<'a> {
+        Box::pin(async move {
+            self.tokens()?.remove(session_id);
+            Ok(())
+        })
+    }
+}
+
+/// A [`TokenStore`] that keeps every session in one JSON file.
+///
+/// Writes go to a temporary file next to `path` which is then renamed over
+/// it, so readers never see a partial file. On Unix the file is created with
+/// `0600` permissions. With the `token-store-encryption` feature,
+/// [`FileTokenStore::with_encryption_key`] encrypts the file with AES-256-GCM.
+///
+/// Stores opened on the same path within one process share a lock, so their
+/// updates never overwrite each other. Separate processes must not share the
+/// file.
+pub struct FileTokenStore {
+    path: PathBuf,
+    #[cfg(feature = "token-store-encryption")]
+    key: Option<[u8; 32]>,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// The lock of each path a [`FileTokenStore`] is open on in this process.
+static PATH_LOCKS: LazyLock<Mutex<HashMap<PathBuf, Weak<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(Mutex::default);
+
+/// Makes temporary file names unique between writers in this process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn path_lock(path: &Path) -> Arc<tokio::sync::Mutex<()>> {
+    let key = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut locks = PATH_LOCKS.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(lock) = locks.get(&key).and_then(Weak::upgrade) {
+        return lock;
+    }
+
+    locks.retain(|_, lock| lock.strong_count() > 0);
+    let lock = Arc::new(tokio::sync::Mutex::new(()));
+    locks.insert(key, Arc::downgrade(&lock));
+    lock
+}
+
+impl std::fmt::Debug for FileTokenStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileTokenStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FileTokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            lock: path_lock(&path),
+            path,
+            #[cfg(feature = "token-store-encryption")]
+            key: None,
+        }
+    }
+
+    /// Encrypts the file at rest with a caller-supplied 256-bit key.
+    #[cfg(feature = "token-store-encryption")]
+    pub fn with_encryption_key(mut self, key: [u8; 32]) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn read(&self) -> Result<BTreeMap<String, TokenData>, ShopifyAPIError> {
+        let bytes = match tokio::fs::read(&self.path).await {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        #[cfg(feature = "token-store-encryption")]
+        let bytes = match &self.key {
+            Some(key) => encryption::decrypt(key, &bytes)?,
+            None => bytes,
+        };
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    async fn write(&self, tokens: &BTreeMap<String, TokenData>) -> Result<(), ShopifyAPIError> {
+        let bytes = serde_json::to_vec_pretty(tokens)?;
+
+        #[cfg(feature = "token-store-encryption")]
+        let bytes = match &self.key {
+            Some(key) => encryption::encrypt(key, &bytes)?,
+            None => bytes,
+        };
+
+        if let Some(parent) = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut temp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = self.path.with_file_name(temp_name);
+
+        let result = write_private_file(&temp_path, &bytes).await;
+        let result = match result {
+            Ok(()) => tokio::fs::rename(&temp_path, &self.path).await,
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+        }
+        result?;
+
+        // The rename is only durable once the directory entry is synced.
+        #[cfg(unix)]
+        {
+            let parent = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            tokio::fs::File::open(parent).await?.sync_all().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn update<F>(&self, update: F) -> Result<(), ShopifyAPIError>
+    where
+        F: FnOnce(&mut BTreeMap<String, TokenData>) -> bool,
+    {
+        let _guard = self.lock.lock().await;
+        let mut tokens = self.read().await?;
+        if update(&mut tokens) {
+            self.write(&tokens).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn write_private_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options.open(path).await?;
+    file.write_all(bytes).await?;
+    file.sync_all().await
+}
+
+impl TokenStore for FileTokenStore {
+    fn save_token<'a>(&'a self, session_id: &'a str, token: TokenData) -> TokenStoreFuture<'a> {
+        Box::pin(self.update(move |tokens| {
+            tokens.insert(session_id.to_string(), token);
+            true
+        }))
+    }
+
+    fn load_token<'a>(&'a self, session_id: &'a str) -> TokenStoreFuture<'a, Option<TokenData>> {
+        Box::pin(async move {
+            let _guard = self.lock.lock().await;
+            Ok(self.read().await?.remove(session_id))
+        })
+    }
+
+    fn delete_token<'a>(&'a self, session_id: &'a str) -> TokenStoreFuture<'a> {
+        Box::pin(self.update(move |tokens| tokens.remove(session_id).is_some()))
+    }
+}
+
+#[cfg(feature = "token-store-encryption")]
+mod encryption {
+    use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit, Nonce};
+    use base64::prelude::*;
+    use serde::{Deserialize, Serialize};
+
+    use crate::ShopifyAPIError;
+
+    #[derive(Serialize, Deserialize)]
+    struct EncryptedFile {
+        nonce: String,
+        ciphertext: String,
+    }
+
+    pub(super) fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, ShopifyAPIError> {
+        let mut nonce = [0; 12];
+        getrandom::fill(&mut nonce)
+            .map_err(|err| ShopifyAPIError::Other(format!("random generation failed: {err}")))?;
+        let ciphertext = Aes256Gcm::new(key.into())
+            .encrypt(&Nonce::from(nonce), plaintext)
+            .map_err(|_| ShopifyAPIError::Other("token store encryption failed".to_string()))?;
+
+        Ok(serde_json::to_vec(&EncryptedFile {
+            nonce: BASE64_STANDARD.encode(nonce),
+            ciphertext: BASE64_STANDARD.encode(ciphertext),
+        })?)
+    }
+
+    pub(super) fn decrypt(key: &[u8; 32], bytes: &[u8]) -> Result<Vec<u8>, ShopifyAPIError> {
+        let invalid = || {
+            ShopifyAPIError::Other("token store file cannot be decrypted with this key".to_string())
+        };
+        let file: EncryptedFile = serde_json::from_slice(bytes).map_err(|_| invalid())?;
+        let nonce: [u8; 12] = BASE64_STANDARD
+            .decode(file.nonce)
+            .ok()
+            .and_then(|nonce| nonce.try_into().ok())
+            .ok_or_else(invalid)?;
+        let ciphertext = BASE64_STANDARD
+            .decode(file.ciphertext)
+            .map_err(|_| invalid())?;
+
+        Aes256Gcm::new(key.into())
+            .decrypt(&Nonce::from(nonce), ciphertext.as_ref())
+            .map_err(|_| invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("shopify_api-{}-{name}", std::process::id()))
+            .join("tokens.json")
+    }
+
+    #[tokio::test]
+    async fn memory_store_round_trips_tokens() {
+        let store = MemoryTokenStore::new();
+        store
+            .save_token("shop", TokenData::never_expiring("token"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.load_token("shop").await.unwrap(),
+            Some(TokenData::never_expiring("token"))
+        );
+        store.delete_token("shop").await.unwrap();
+        assert_eq!(store.load_token("shop").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn file_store_round_trips_tokens_with_private_permissions() {
+        let path = temp_path("plain");
+        let store = FileTokenStore::new(&path);
+        assert_eq!(store.load_token("shop").await.unwrap(), None);
+
+        store
+            .save_token("shop", TokenData::never_expiring("one"))
+            .await
+            .unwrap();
+        store
+            .save_token("other", TokenData::never_expiring("two"))
+            .await
+            .unwrap();
+        store.delete_token("other").await.unwrap();
+
+        let reopened = FileTokenStore::new(&path);
+        assert_eq!(
+            reopened.load_token("shop").await.unwrap(),
+            Some(TokenData::never_expiring("one"))
+        );
+        assert_eq!(reopened.load_token("other").await.unwrap(), None);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn file_stores_on_the_same_path_keep_each_others_updates() {
+        let path = temp_path("shared");
+        let writers = (0..16)
+            .map(|id| {
+                let path = path.clone();
+                tokio::spawn(async move {
+                    FileTokenStore::new(path)
+                        .save_token(&format!("shop-{id}"), TokenData::never_expiring("token"))
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.await.unwrap().unwrap();
+        }
+
+        let store = FileTokenStore::new(&path);
+        for id in 0..16 {
+            assert!(store
+                .load_token(&format!("shop-{id}"))
+                .await
+                .unwrap()
+                .is_some());
+        }
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(feature = "token-store-encryption")]
+    #[tokio::test]
+    async fn encrypted_file_store_hides_tokens() {
+        let path = temp_path("encrypted");
+        let store = FileTokenStore::new(&path).with_encryption_key([7; 32]);
+        store
+            .save_token("shop", TokenData::never_expiring("secret-token"))
+            .await
+            .unwrap();
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("secret-token"));
+        assert_eq!(
+            store.load_token("shop").await.unwrap(),
+            Some(TokenData::never_expiring("secret-token"))
+        );
+        assert!(FileTokenStore::new(&path)
+            .with_encryption_key([8; 32])
+            .load_token("shop")
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::Duration,
+};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+
+use crate::{
+    normalize_shop_domain, offline_session_id, throttle,
+    transport::{HttpTransport, ReqwestTransport},
+    Shopify, ShopifyAPIError, ShopifyAuth, ShopifyConfig,
+};
+
+/// Builds a [`Shopify`] client with a custom HTTP client, transport, base URL,
+/// timeouts or default headers.
+///
+/// ```rust,ignore
+/// let shopify = Shopify::builder("my-shop", ShopifyAuth::AccessToken(token))
+///     .base_url("http://127.0.0.1:8080")
+///     .timeout(Duration::from_secs(30))
+///     .build()?;
+/// ```
+pub struct ShopifyBuilder {
+    shop: String,
+    auth: ShopifyAuth,
+    config: ShopifyConfig,
+    client: Option<reqwest::Client>,
+    transport: Option<Arc<dyn HttpTransport>>,
+    base_url: Option<String>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    headers: HeaderMap,
+}
+
+impl std::fmt::Debug for ShopifyBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShopifyBuilder")
+            .field("shop", &self.shop)
+            .field("auth", &self.auth)
+            .field("base_url", &self.base_url)
+            .field("timeout", &self.timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Shopify {
+    pub fn builder(shop: impl AsRef<str>, auth: ShopifyAuth) -> ShopifyBuilder {
+        ShopifyBuilder {
+            shop: shop.as_ref().to_string(),
+            auth,
+            config: ShopifyConfig::default(),
+            client: None,
+            transport: None,
+            base_url: None,
+            timeout: None,
+            connect_timeout: None,
+            headers: HeaderMap::new(),
+        }
+    }
+}
+
+impl ShopifyBuilder {
+    pub fn config(mut self, config: ShopifyConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sends requests with an existing client, for example one configured
+    /// with a proxy, mTLS or connection-pool settings.
+    /// [`ShopifyBuilder::connect_timeout`] does not apply to it.
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Sends requests through `transport` instead of reqwest.
+    pub fn transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Replaces `https://{shop}.myshopify.com` in the Admin API and token
+    /// URLs, for example with a local mock server.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// Total time allowed for each request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Adds a header sent with every request.
+    pub fn default_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    pub fn build(self) -> Result<Shopify, ShopifyAPIError> {
+        let config = self.config;
+        let shop_domain = normalize_shop_domain(&self.shop);
+        let base_url = match self.base_url {
+            Some(base_url) => base_url.trim_end_matches('/').to_string(),
+            None => format!("https://{shop_domain}"),
+        };
+        let query_url = format!("{base_url}/admin/api/{}/graphql.json", config.api_version);
+        let token_url = format!("{base_url}/admin/oauth/access_token");
+        let session_id = config
+            .session_id
+            .unwrap_or_else(|| offline_session_id(&shop_domain));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_str(&config.user_agent)?);
+        headers.extend(self.headers);
+
+        let transport: Arc<dyn HttpTransport> = match (self.transport, self.client) {
+            (Some(transport), _) => transport,
+            (None, Some(client)) => Arc::new(ReqwestTransport::new(client)),
+            (None, None) => {
+                let mut client = reqwest::Client::builder();
+                if let Some(timeout) = self.connect_timeout {
+                    client = client.connect_timeout(timeout);
+                }
+                Arc::new(ReqwestTransport::new(client.build()?))
+            }
+        };
+
+        Ok(Shopify {
+            api_version: config.api_version,
+            #[cfg(feature = "webhooks")]
+            shared_secret: config.shared_secret,
+            auth: Arc::new(Mutex::new(self.auth)),
+            transport,
+            default_headers: Arc::new(headers),
+            timeout: self.timeout,
+            query_url,
+            token_url,
+            shop: self.shop,
+            shop_domain,
+            token_store: config.token_store,
+            session_id,
+            load_token_from_store: config.load_token_from_store,
+            token_loaded: Arc::new(AtomicBool::new(false)),
+            refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
+            token_refresh_leeway: config.token_refresh_leeway,
+            throttle: config
+                .throttle
+                .map(|throttle| Arc::new(throttle::CostLimiter::new(throttle))),
+            retry: config.retry,
+            idempotent: false,
+            metrics: config.metrics,
+            middleware: config.middleware.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::transport::{HttpRequest, HttpResponse, TransportFuture};
+
+    #[derive(Default)]
+    struct ScriptedTransport {
+        responses: Mutex<VecDeque<(StatusCode, serde_json::Value)>>,
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl HttpTransport for ScriptedTransport {
+        fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+            self.requests.lock().unwrap().push(request);
+            let (status, body) = self.responses.lock().unwrap().pop_front().unwrap();
+            Box::pin(async move {
+                Ok(HttpResponse::new(
+                    status,
+                    HeaderMap::new(),
+                    body.to_string(),
+                ))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_through_transport_with_base_url_headers_and_token_replay() {
+        let token = |value: &str| json!({"access_token": value, "expires_in": 86399});
+        let transport = Arc::new(ScriptedTransport {
+            responses: Mutex::new(VecDeque::from([
+                (StatusCode::OK, token("first")),
+                (StatusCode::UNAUTHORIZED, json!({"errors": "revoked"})),
+                (StatusCode::OK, token("second")),
+                (StatusCode::OK, json!({"data": {"shop": {"name": "Mock"}}})),
+            ])),
+            ..Default::default()
+        });
+        let shopify = Shopify::builder(
+            "my-shop",
+            ShopifyAuth::client_credentials("client-id", "secret"),
+        )
+        .transport(transport.clone())
+        .base_url("http://mock.test/")
+        .default_header(
+            HeaderName::from_static("x-test"),
+            HeaderValue::from_static("yes"),
+        )
+        .build()
+        .unwrap();
+
+        let name: String = shopify
+            .graphql_at_path(
+                "{ shop { name } }",
+                &json!({}),
+                &[
+                    crate::utils::ReadJsonTreeSteps::Key("shop"),
+                    crate::utils::ReadJsonTreeSteps::Key("name"),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(name, "Mock");
+
+        let requests = transport.requests.lock().unwrap();
+        let urls = requests
+            .iter()
+            .map(|request| request.url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "http://mock.test/admin/oauth/access_token",
+                "http://mock.test/admin/api/2026-04/graphql.json",
+                "http://mock.test/admin/oauth/access_token",
+                "http://mock.test/admin/api/2026-04/graphql.json",
+            ]
+        );
+        assert_eq!(requests[1].headers["x-shopify-access-token"], "first");
+        assert_eq!(requests[3].headers["x-shopify-access-token"], "second");
+        assert!(requests
+            .iter()
+            .all(|request| request.headers["x-test"] == "yes"));
+    }
+}
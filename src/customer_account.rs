@@ -0,0 +1,842 @@
+//! A client for the Customer Account API, which acts on behalf of a customer
+//! logged in to a shop.
+//!
+//! Customers log in through Shopify's OpenID Connect provider with the
+//! authorization code grant and PKCE:
+//!
+//! 1. Redirect the customer to the URL of
+//!    [`ShopifyCustomerAccount::begin_login`], keeping the returned `state`,
+//!    `nonce` and `code_verifier` in their session.
+//! 2. Check the redirect back with [`ShopifyCustomerAccount::callback_code`].
+//! 3. Trade the code for a token with
+//!    [`ShopifyCustomerAccount::complete_login`], which checks the `nonce` of
+//!    the ID token and saves the token in the [`TokenStore`].
+//!
+//! ```rust,ignore
+//! let customers = ShopifyCustomerAccount::new(
+//!     "my-shop",
+//!     CustomerAccountConfig::new(client_id, "https://app.test/account/callback"),
+//! )?
+//! .with_token_store(token_store);
+//!
+//! let login = customers.begin_login().await?;
+//! // ... redirect to login.url, then on the callback:
+//! let code = ShopifyCustomerAccount::callback_code(&query, &login.state)?;
+//! customers
+//!     .complete_login(&session_id, &code, &login.code_verifier, &login.nonce)
+//!     .await?;
+//!
+//! let token = customers.load_token(&session_id).await?.unwrap();
+//! let customer: Value = customers
+//!     .graphql(&token, "{ customer { emailAddress { emailAddress } } }", &json!({}))
+//!     .await?;
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
+
+use base64::prelude::*;
+use reqwest::header::{HeaderValue, AUTHORIZATION, USER_AGENT};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::{
+    auth::request_token,
+    normalize_shop_domain, retry,
+    transport::{HttpRequest, HttpResponse, HttpTransport, ReqwestTransport},
+    utils::{error_for_status, ReadJsonTreeSteps},
+    ApiVersion, GraphqlResponse, GraphqlWithMeta, RetryPolicy, ShopifyAPIError, TokenData,
+    TokenStore, VERSION,
+};
+
+/// Settings of a [`ShopifyCustomerAccount`] client, from the Customer Account
+/// API settings of the shop's Headless or Hydrogen channel.
+#[derive(Clone)]
+pub struct CustomerAccountConfig {
+    pub client_id: String,
+    /// Set for confidential clients, which authenticate token requests with
+    /// HTTP Basic auth. Public clients rely on PKCE alone.
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub api_version: ApiVersion,
+    pub user_agent: String,
+    pub retry: RetryPolicy,
+    /// How long before expiry [`ShopifyCustomerAccount::load_token`] refreshes
+    /// a token.
+    pub token_refresh_leeway: chrono::Duration,
+}
+
+impl CustomerAccountConfig {
+    /// A public client requesting the `openid`, `email` and
+    /// `customer-account-api:full` scopes.
+    pub fn new(client_id: impl Into<String>, redirect_uri: impl Into<String>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            client_secret: None,
+            redirect_uri: redirect_uri.into(),
+            scopes: ["openid", "email", "customer-account-api:full"]
+                .map(str::to_string)
+                .to_vec(),
+            api_version: ApiVersion::default(),
+            user_agent: VERSION.to_string(),
+            retry: RetryPolicy::default(),
+            token_refresh_leeway: chrono::Duration::minutes(1),
+        }
+    }
+}
+
+impl std::fmt::Debug for CustomerAccountConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomerAccountConfig")
+            .field("client_id", &self.client_id)
+            .field(
+                "client_secret",
+                &self.client_secret.as_ref().map(|_| "<redacted>"),
+            )
+            .field("redirect_uri", &self.redirect_uri)
+            .field("scopes", &self.scopes)
+            .field("api_version", &self.api_version)
+            .field("retry", &self.retry)
+            .field("token_refresh_leeway", &self.token_refresh_leeway)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The endpoints a shop advertises under `/.well-known/`.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct CustomerAccountEndpoints {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub end_session_endpoint: String,
+    /// The GraphQL endpoint for [`CustomerAccountConfig::api_version`].
+    #[serde(default)]
+    pub graphql_api: String,
+}
+
+#[derive(Deserialize)]
+struct CustomerAccountApiDiscovery {
+    graphql_api: String,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    nonce: Option<String>,
+}
+
+/// A login to redirect the customer to. `state`, `nonce` and `code_verifier`
+/// must be kept in the customer's session until the callback.
+#[derive(Clone, Eq, PartialEq)]
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+impl std::fmt::Debug for AuthorizationRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthorizationRequest")
+            .field("url", &self.url)
+            .field("state", &self.state)
+            .field("nonce", &self.nonce)
+            .field("code_verifier", &"<redacted>")
+            .finish()
+    }
+}
+
+/// A client for the Customer Account API of one shop.
+///
+/// Endpoints are discovered on first use and shared between clones, as are
+/// the per-session locks that keep rotated refresh tokens from being spent
+/// twice.
+#[derive(Clone)]
+pub struct ShopifyCustomerAccount {
+    config: CustomerAccountConfig,
+    shop_domain: String,
+    user_agent: HeaderValue,
+    transport: Arc<dyn HttpTransport>,
+    token_store: Option<Arc<dyn TokenStore>>,
+    endpoints: Arc<OnceCell<CustomerAccountEndpoints>>,
+    refresh_locks: Arc<std::sync::Mutex<HashMap<String, Weak<Mutex<()>>>>>,
+}
+
+impl std::fmt::Debug for ShopifyCustomerAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShopifyCustomerAccount")
+            .field("config", &self.config)
+            .field("shop_domain", &self.shop_domain)
+            .field("endpoints", &self.endpoints.get())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ShopifyCustomerAccount {
+    pub fn new(
+        shop: impl AsRef<str>,
+        config: CustomerAccountConfig,
+    ) -> Result<Self, ShopifyAPIError> {
+        Ok(Self {
+            shop_domain: normalize_shop_domain(shop.as_ref()),
+            user_agent: HeaderValue::from_str(&config.user_agent)?,
+            config,
+            transport: Arc::new(ReqwestTransport::default()),
+            token_store: None,
+            endpoints: Arc::new(OnceCell::new()),
+            refresh_locks: Arc::default(),
+        })
+    }
+
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Stores customer tokens by session id for
+    /// [`ShopifyCustomerAccount::complete_login`],
+    /// [`ShopifyCustomerAccount::load_token`] and
+    /// [`ShopifyCustomerAccount::logout`].
+    pub fn with_token_store(mut self, token_store: Arc<dyn TokenStore>) -> Self {
+        self.token_store = Some(token_store);
+        self
+    }
+
+    /// Uses known endpoints instead of discovering them.
+    pub fn with_endpoints(mut self, endpoints: CustomerAccountEndpoints) -> Self {
+        self.endpoints = Arc::new(OnceCell::new_with(Some(endpoints)));
+        self
+    }
+
+    pub fn shop_domain(&self) -> &str {
+        &self.shop_domain
+    }
+
+    pub fn config(&self) -> &CustomerAccountConfig {
+        &self.config
+    }
+
+    /// The shop's endpoints, read from `/.well-known/openid-configuration`
+    /// and `/.well-known/customer-account-api` on first use.
+    pub async fn endpoints(&self) -> Result<&CustomerAccountEndpoints, ShopifyAPIError> {
+        self.endpoints.get_or_try_init(|| self.discover()).await
+    }
+
+    async fn discover(&self) -> Result<CustomerAccountEndpoints, ShopifyAPIError> {
+        let mut endpoints: CustomerAccountEndpoints = self
+            .get_json(&format!(
+                "https://{}/.well-known/openid-configuration",
+                self.shop_domain
+            ))
+            .await?;
+        let api: CustomerAccountApiDiscovery = self
+            .get_json(&format!(
+                "https://{}/.well-known/customer-account-api",
+                self.shop_domain
+            ))
+            .await?;
+        endpoints.graphql_api = versioned_graphql_url(&api.graphql_api, &self.config.api_version);
+        Ok(endpoints)
+    }
+
+    async fn get_json<T>(&self, url: &str) -> Result<T, ShopifyAPIError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.config
+            .retry
+            .run(|| async {
+                let response = self.send(HttpRequest::get(url)).await?;
+                error_for_status(response).await?.json().await
+            })
+            .await
+    }
+
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, ShopifyAPIError> {
+        self.transport
+            .send(request.header(USER_AGENT, self.user_agent.clone()))
+            .await
+    }
+
+    /// Builds the login URL with a fresh `state`, `nonce` and PKCE verifier.
+    pub async fn begin_login(&self) -> Result<AuthorizationRequest, ShopifyAPIError> {
+        let state = random_token(16)?;
+        let nonce = random_token(16)?;
+        let code_verifier = random_token(32)?;
+
+        let mut url = reqwest::Url::parse(&self.endpoints().await?.authorization_endpoint)
+            .map_err(|err| ShopifyAPIError::Other(err.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("response_type", "code")
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &code_challenge(&code_verifier))
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(AuthorizationRequest {
+            url: url.into(),
+            state,
+            nonce,
+            code_verifier,
+        })
+    }
+
+    /// Returns the authorization code of the raw query string the customer
+    /// was redirected to `redirect_uri` with, after checking its `state`.
+    pub fn callback_code(query: &str, expected_state: &str) -> Result<String, ShopifyAPIError> {
+        let params = reqwest::Url::parse(&format!("https://callback.invalid/?{query}"))
+            .map_err(|err| ShopifyAPIError::InvalidOAuthCallback(err.to_string()))?
+            .query_pairs()
+            .into_owned()
+            .collect::<HashMap<_, _>>();
+        let invalid = |reason: String| ShopifyAPIError::InvalidOAuthCallback(reason);
+
+        if let Some(error) = params.get("error") {
+            let description = params
+                .get("error_description")
+                .map(|description| format!(": {description}"))
+                .unwrap_or_default();
+            return Err(invalid(format!("{error}{description}")));
+        }
+        if params.get("state").map(String::as_str) != Some(expected_state) {
+            return Err(invalid("state does not match".to_string()));
+        }
+        params
+            .get("code")
+            .cloned()
+            .ok_or_else(|| invalid("missing code".to_string()))
+    }
+
+    /// Exchanges the authorization code for a token, after checking that its
+    /// ID token carries the `nonce` of the login.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<TokenData, ShopifyAPIError> {
+        // Authorization codes are single-use.
+        let token = self
+            .request_token(
+                &self.config.retry.unprocessed_only(),
+                &[
+                    ("grant_type", "authorization_code"),
+                    ("client_id", self.config.client_id.as_str()),
+                    ("redirect_uri", self.config.redirect_uri.as_str()),
+                    ("code", code),
+                    ("code_verifier", code_verifier),
+                ],
+            )
+            .await?;
+        self.verify_nonce(&token, nonce)?;
+        Ok(token)
+    }
+
+    /// Checks the `nonce` claim of the ID token. The token comes straight
+    /// from the token endpoint over TLS, so its signature is not checked
+    /// (OpenID Connect Core 1.0, section 3.1.3.7).
+    fn verify_nonce(&self, token: &TokenData, nonce: &str) -> Result<(), ShopifyAPIError> {
+        let invalid = |reason: &str| ShopifyAPIError::InvalidOAuthCallback(reason.to_string());
+        let Some(id_token) = &token.id_token else {
+            return if self.config.scopes.iter().any(|scope| scope == "openid") {
+                Err(invalid("token response has no id_token"))
+            } else {
+                Ok(())
+            };
+        };
+
+        let claims = id_token
+            .split('.')
+            .nth(1)
+            .and_then(|payload| BASE64_URL_SAFE_NO_PAD.decode(payload).ok())
+            .and_then(|payload| serde_json::from_slice::<IdTokenClaims>(&payload).ok())
+            .ok_or_else(|| invalid("malformed id_token"))?;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(invalid("nonce does not match"));
+        }
+        Ok(())
+    }
+
+    /// Exchanges the authorization code and saves the token under
+    /// `session_id`.
+    pub async fn complete_login(
+        &self,
+        session_id: &str,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<TokenData, ShopifyAPIError> {
+        let token = self.exchange_code(code, code_verifier, nonce).await?;
+        self.token_store()?
+            .save_token(session_id, token.clone())
+            .await?;
+        Ok(token)
+    }
+
+    /// Trades the refresh token of `token` for a new token. The ID token is
+    /// kept when the response does not carry a new one.
+    pub async fn refresh(&self, token: &TokenData) -> Result<TokenData, ShopifyAPIError> {
+        let refresh_token = token.refresh_token.as_deref().ok_or_else(|| {
+            ShopifyAPIError::Authentication("customer token has no refresh token".to_string())
+        })?;
+        // Refresh tokens rotate, so a replayed request would spend it twice.
+        let mut refreshed = self
+            .request_token(
+                &self.config.retry.unprocessed_only(),
+                &[
+                    ("grant_type", "refresh_token"),
+                    ("client_id", self.config.client_id.as_str()),
+                    ("refresh_token", refresh_token),
+                ],
+            )
+            .await?;
+        refreshed.id_token = refreshed.id_token.or_else(|| token.id_token.clone());
+        Ok(refreshed)
+    }
+
+    async fn request_token(
+        &self,
+        retry: &RetryPolicy,
+        form: &[(&str, &str)],
+    ) -> Result<TokenData, ShopifyAPIError> {
+        let token_url = &self.endpoints().await?.token_endpoint;
+        let basic_auth = self
+            .config
+            .client_secret
+            .as_ref()
+            .map(|secret| {
+                let credentials = format!("{}:{secret}", self.config.client_id);
+                let mut value = HeaderValue::from_str(&format!(
+                    "Basic {}",
+                    BASE64_STANDARD.encode(credentials)
+                ))?;
+                value.set_sensitive(true);
+                Ok::<_, ShopifyAPIError>(value)
+            })
+            .transpose()?;
+
+        request_token(
+            |request| async {
+                match &basic_auth {
+                    Some(value) => {
+                        self.send(request.header(AUTHORIZATION, value.clone()))
+                            .await
+                    }
+                    None => self.send(request).await,
+                }
+            },
+            retry,
+            token_url,
+            form,
+        )
+        .await
+    }
+
+    fn token_store(&self) -> Result<&Arc<dyn TokenStore>, ShopifyAPIError> {
+        self.token_store.as_ref().ok_or_else(|| {
+            ShopifyAPIError::Other("ShopifyCustomerAccount has no token store".to_string())
+        })
+    }
+
+    /// Loads the token saved under `session_id`, refreshing and saving it
+    /// first when it expires within
+    /// [`CustomerAccountConfig::token_refresh_leeway`].
+    pub async fn load_token(&self, session_id: &str) -> Result<Option<TokenData>, ShopifyAPIError> {
+        let store = self.token_store()?;
+        match store.load_token(session_id).await? {
+            Some(token) if token.expires_within(self.config.token_refresh_leeway) => {}
+            token => return Ok(token),
+        }
+
+        let lock = self.refresh_lock(session_id);
+        let _refresh = lock.lock().await;
+        // Another caller may have refreshed the token while this one waited.
+        let Some(token) = store.load_token(session_id).await? else {
+            return Ok(None);
+        };
+        if !token.expires_within(self.config.token_refresh_leeway) {
+            return Ok(Some(token));
+        }
+
+        let token = self.refresh(&token).await?;
+        store.save_token(session_id, token.clone()).await?;
+        Ok(Some(token))
+    }
+
+    fn refresh_lock(&self, session_id: &str) -> Arc<Mutex<()>> {
+        let mut locks = self
+            .refresh_locks
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if let Some(lock) = locks.get(session_id).and_then(Weak::upgrade) {
+            return lock;
+        }
+
+        locks.retain(|_, lock| lock.strong_count() > 0);
+        let lock = Arc::new(Mutex::new(()));
+        locks.insert(session_id.to_string(), Arc::downgrade(&lock));
+        lock
+    }
+
+    /// The URL that ends the customer's session with Shopify, then sends them
+    /// to `post_logout_redirect_uri`.
+    pub async fn logout_url(
+        &self,
+        token: &TokenData,
+        post_logout_redirect_uri: Option<&str>,
+    ) -> Result<String, ShopifyAPIError> {
+        let mut url = reqwest::Url::parse(&self.endpoints().await?.end_session_endpoint)
+            .map_err(|err| ShopifyAPIError::Other(err.to_string()))?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(id_token) = &token.id_token {
+                query.append_pair("id_token_hint", id_token);
+            }
+            if let Some(redirect_uri) = post_logout_redirect_uri {
+                query.append_pair("post_logout_redirect_uri", redirect_uri);
+            }
+        }
+        Ok(url.into())
+    }
+
+    /// Deletes the token saved under `session_id` and returns the URL to
+    /// redirect the customer to, if a token was saved.
+    pub async fn logout(
+        &self,
+        session_id: &str,
+        post_logout_redirect_uri: Option<&str>,
+    ) -> Result<Option<String>, ShopifyAPIError> {
+        let store = self.token_store()?;
+        let Some(token) = store.load_token(session_id).await? else {
+            return Ok(None);
+        };
+        store.delete_token(session_id).await?;
+        Ok(Some(
+            self.logout_url(&token, post_logout_redirect_uri).await?,
+        ))
+    }
+
+    /// Sends a GraphQL request with the customer's access token and returns
+    /// the raw response. A rejected token returns
+    /// [`ShopifyAPIError::InvalidAccessToken`].
+    pub async fn graphql_raw<Variables>(
+        &self,
+        token: &TokenData,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<GraphqlResponse<Value>, ShopifyAPIError>
+    where
+        Variables: serde::Serialize,
+    {
+        let mut access_token = HeaderValue::from_str(&token.access_token)?;
+        access_token.set_sensitive(true);
+        let request = HttpRequest::post(&self.endpoints().await?.graphql_api)
+            .json(&serde_json::json!({ "query": query, "variables": variables }))?
+            .header(AUTHORIZATION, access_token);
+        let retry = if retry::is_mutation(query) {
+            self.config.retry.unprocessed_only()
+        } else {
+            self.config.retry
+        };
+
+        retry
+            .run(|| async {
+                let response = self.send(request.clone()).await?;
+                if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+                    return Err(ShopifyAPIError::InvalidAccessToken);
+                }
+                error_for_status(response).await?.json().await
+            })
+            .await
+    }
+
+    pub async fn graphql<ReturnType, Variables>(
+        &self,
+        token: &TokenData,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<ReturnType, ShopifyAPIError>
+    where
+        ReturnType: serde::de::DeserializeOwned,
+        Variables: serde::Serialize,
+    {
+        Ok(self.graphql_with_meta(token, query, variables).await?.data)
+    }
+
+    pub async fn graphql_with_meta<ReturnType, Variables>(
+        &self,
+        token: &TokenData,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<GraphqlWithMeta<ReturnType>, ShopifyAPIError>
+    where
+        ReturnType: serde::de::DeserializeOwned,
+        Variables: serde::Serialize,
+    {
+        self.graphql_raw(token, query, variables).await?.into_data()
+    }
+
+    pub async fn graphql_at_path<ReturnType, Variables>(
+        &self,
+        token: &TokenData,
+        query: &str,
+        variables: &Variables,
+        json_finder: &[ReadJsonTreeSteps<'_>],
+    ) -> Result<ReturnType, ShopifyAPIError>
+    where
+        ReturnType: serde::de::DeserializeOwned,
+        Variables: serde::Serialize,
+    {
+        Ok(self
+            .graphql_with_meta::<Value, _>(token, query, variables)
+            .await?
+            .at_path(json_finder)?
+            .data)
+    }
+}
+
+/// Random bytes encoded as unpadded base64url, as RFC 7636 asks of PKCE
+/// verifiers.
+fn random_token(len: usize) -> Result<String, ShopifyAPIError> {
+    let mut bytes = vec![0; len];
+    getrandom::fill(&mut bytes)
+        .map_err(|err| ShopifyAPIError::Other(format!("random generation failed: {err}")))?;
+    Ok(BASE64_URL_SAFE_NO_PAD.encode(bytes))
+}
+
+fn code_challenge(code_verifier: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Replaces the version in a discovered `.../api/{version}/graphql` URL.
+fn versioned_graphql_url(url: &str, api_version: &ApiVersion) -> String {
+    let Some((base, rest)) = url.rsplit_once("/api/") else {
+        return url.to_string();
+    };
+    match rest.split_once('/') {
+        Some((_, path)) => format!("{base}/api/{}/{path}", api_version.as_str()),
+        None => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, sync::Mutex};
+
+    use reqwest::{header::HeaderMap, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{transport::TransportFuture, MemoryTokenStore};
+
+    #[derive(Default)]
+    struct ScriptedTransport {
+        responses: Mutex<VecDeque<Value>>,
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl HttpTransport for ScriptedTransport {
+        fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+            self.requests.lock().unwrap().push(request);
+            let body = self.responses.lock().unwrap().pop_front().unwrap();
+            Box::pin(async move {
+                Ok(HttpResponse::new(
+                    StatusCode::OK,
+                    HeaderMap::new(),
+                    body.to_string(),
+                ))
+            })
+        }
+    }
+
+    fn form(request: &HttpRequest) -> HashMap<String, String> {
+        reqwest::Url::parse(&format!(
+            "https://form.invalid/?{}",
+            request.body_text().unwrap()
+        ))
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect()
+    }
+
+    fn id_token(nonce: &str) -> String {
+        let claims = BASE64_URL_SAFE_NO_PAD.encode(json!({ "nonce": nonce }).to_string());
+        format!("eyJhbGciOiJSUzI1NiJ9.{claims}.signature")
+    }
+
+    #[test]
+    fn code_challenge_matches_rfc_7636_example() {
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn callback_code_checks_state_and_errors() {
+        let code = ShopifyCustomerAccount::callback_code("code=abc&state=s1", "s1").unwrap();
+        assert_eq!(code, "abc");
+        assert!(matches!(
+            ShopifyCustomerAccount::callback_code("code=abc&state=s2", "s1"),
+            Err(ShopifyAPIError::InvalidOAuthCallback(_))
+        ));
+        assert!(matches!(
+            ShopifyCustomerAccount::callback_code("error=access_denied&state=s1", "s1"),
+            Err(ShopifyAPIError::InvalidOAuthCallback(reason)) if reason == "access_denied"
+        ));
+    }
+
+    #[tokio::test]
+    async fn logs_in_refreshes_queries_and_logs_out_a_customer() {
+        let transport = Arc::new(ScriptedTransport {
+            responses: Mutex::new(VecDeque::from([
+                json!({
+                    "issuer": "https://shopify.com/1",
+                    "authorization_endpoint": "https://shopify.com/1/auth/oauth/authorize",
+                    "token_endpoint": "https://shopify.com/1/auth/oauth/token",
+                    "end_session_endpoint": "https://shopify.com/1/auth/logout",
+                }),
+                json!({"graphql_api": "https://shopify.com/1/account/customer/api/unstable/graphql"}),
+            ])),
+            ..Default::default()
+        });
+        let store = Arc::new(MemoryTokenStore::new());
+        let customers = ShopifyCustomerAccount::new(
+            "my-shop",
+            CustomerAccountConfig {
+                client_secret: Some("secret".to_string()),
+                ..CustomerAccountConfig::new("client-id", "https://app.test/callback")
+            },
+        )
+        .unwrap()
+        .with_transport(transport.clone())
+        .with_token_store(store.clone());
+
+        let login = customers.begin_login().await.unwrap();
+        let url = reqwest::Url::parse(&login.url).unwrap();
+        let params = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+        assert_eq!(url.path(), "/1/auth/oauth/authorize");
+        assert_eq!(params["state"], login.state);
+        assert_eq!(
+            params["code_challenge"],
+            code_challenge(&login.code_verifier)
+        );
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(params["scope"], "openid email customer-account-api:full");
+        assert_eq!(params["nonce"], login.nonce);
+
+        let id_token = id_token(&login.nonce);
+        transport.responses.lock().unwrap().extend([
+            json!({
+                "access_token": "shcat_first",
+                "expires_in": 30,
+                "refresh_token": "refresh-1",
+                "id_token": id_token,
+            }),
+            json!({
+                "access_token": "shcat_second",
+                "expires_in": 3600,
+                "refresh_token": "refresh-2",
+            }),
+            json!({"data": {"customer": {"firstName": "Ada"}}}),
+        ]);
+        customers
+            .complete_login("customer-1", "code", &login.code_verifier, &login.nonce)
+            .await
+            .unwrap();
+        let token = customers.load_token("customer-1").await.unwrap().unwrap();
+        assert_eq!(token.access_token, "shcat_second");
+        assert_eq!(token.id_token.as_deref(), Some(id_token.as_str()));
+
+        let name: String = customers
+            .graphql_at_path(
+                &token,
+                "{ customer { firstName } }",
+                &json!({}),
+                &[
+                    ReadJsonTreeSteps::Key("customer"),
+                    ReadJsonTreeSteps::Key("firstName"),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(name, "Ada");
+
+        let logout = customers
+            .logout("customer-1", Some("https://app.test/"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(logout.starts_with(&format!(
+            "https://shopify.com/1/auth/logout?id_token_hint={id_token}"
+        )));
+        assert!(store.load_token("customer-1").await.unwrap().is_none());
+
+        let requests = transport.requests.lock().unwrap();
+        let exchange = form(&requests[2]);
+        assert_eq!(exchange["grant_type"], "authorization_code");
+        assert_eq!(exchange["code_verifier"], login.code_verifier);
+        assert_eq!(
+            requests[2].headers[AUTHORIZATION],
+            format!("Basic {}", BASE64_STANDARD.encode("client-id:secret"))
+        );
+        assert_eq!(form(&requests[3])["refresh_token"], "refresh-1");
+        assert_eq!(
+            requests[4].url,
+            format!(
+                "https://shopify.com/1/account/customer/api/{}/graphql",
+                ApiVersion::default()
+            )
+        );
+        assert_eq!(requests[4].headers[AUTHORIZATION], "shcat_second");
+        assert!(requests.iter().all(|request| request
+            .headers
+            .get(USER_AGENT)
+            .is_some_and(|agent| agent == VERSION)));
+    }
+
+    #[tokio::test]
+    async fn login_rejects_an_id_token_for_another_nonce() {
+        let transport = Arc::new(ScriptedTransport {
+            responses: Mutex::new(VecDeque::from([
+                json!({"access_token": "shcat_1", "id_token": id_token("other")}),
+                json!({"access_token": "shcat_2"}),
+            ])),
+            ..Default::default()
+        });
+        let store = Arc::new(MemoryTokenStore::new());
+        let customers = ShopifyCustomerAccount::new(
+            "my-shop",
+            CustomerAccountConfig::new("client-id", "https://app.test/callback"),
+        )
+        .unwrap()
+        .with_transport(transport)
+        .with_token_store(store.clone())
+        .with_endpoints(CustomerAccountEndpoints {
+            issuer: "https://shopify.com/1".to_string(),
+            authorization_endpoint: "https://shopify.com/1/auth/oauth/authorize".to_string(),
+            token_endpoint: "https://shopify.com/1/auth/oauth/token".to_string(),
+            end_session_endpoint: "https://shopify.com/1/auth/logout".to_string(),
+            graphql_api: "https://shopify.com/1/account/customer/api/unstable/graphql".to_string(),
+        });
+
+        for _ in 0..2 {
+            let result = customers
+                .complete_login("customer-1", "code", "verifier", "expected")
+                .await;
+            assert!(matches!(
+                result,
+                Err(ShopifyAPIError::InvalidOAuthCallback(_))
+            ));
+        }
+        assert!(store.load_token("customer-1").await.unwrap().is_none());
+    }
+}
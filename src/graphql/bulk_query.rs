@@ -1,9 +1,22 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    path::Path,
+    time::{Duration, Instant},
+};
 
+use futures_util::{stream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tokio::io::AsyncWriteExt;
 
-use crate::{Shopify, ShopifyAPIError};
+use super::{Connection, Edge, PaginateOptions};
+use crate::{
+    telemetry,
+    transport::{HttpRequest, HttpResponse, HttpTransport, MultipartForm, ReqwestTransport},
+    utils::{error_for_status, ReadJsonTreeSteps},
+    MetricLabels, RetryPolicy, Shopify, ShopifyAPIError,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
@@ -101,6 +114,16 @@ pub struct ShopifyBulkOperation {
     pub file_size: Option<String>,
 }
 
+impl ShopifyBulkOperation {
+    /// Time from `createdAt` to `completedAt`, once the operation finished.
+    pub fn duration(&self) -> Option<Duration> {
+        let created_at = chrono::DateTime::parse_from_rfc3339(self.created_at.as_deref()?).ok()?;
+        let completed_at =
+            chrono::DateTime::parse_from_rfc3339(self.completed_at.as_deref()?).ok()?;
+        (completed_at - created_at).to_std().ok()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BulkOperationPayload {
     #[serde(rename = "bulkOperation")]
@@ -134,11 +157,15 @@ pub struct BulkOperationsFilter {
 #[derive(Debug, Clone)]
 pub struct BulkConcurrencyOptions {
     pub max_concurrent: usize,
+    pub wait: BulkWaitOptions,
 }
 
 impl Default for BulkConcurrencyOptions {
     fn default() -> Self {
-        Self { max_concurrent: 5 }
+        Self {
+            max_concurrent: 5,
+            wait: BulkWaitOptions::default(),
+        }
     }
 }
 
@@ -166,26 +193,8 @@ struct BulkOperationsData {
     bulk_operations: BulkOperationConnection,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BulkOperationConnection {
-    pub edges: Vec<BulkOperationEdge>,
-    #[serde(rename = "pageInfo")]
-    pub page_info: PageInfo,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BulkOperationEdge {
-    pub cursor: String,
-    pub node: ShopifyBulkOperation,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct PageInfo {
-    #[serde(rename = "hasNextPage")]
-    pub has_next_page: bool,
-    #[serde(rename = "endCursor")]
-    pub end_cursor: Option<String>,
-}
+pub type BulkOperationConnection = Connection<ShopifyBulkOperation>;
+pub type BulkOperationEdge = Edge<ShopifyBulkOperation>;
 
 #[derive(Debug, Deserialize)]
 struct StagedUploadsData {
@@ -202,60 +211,77 @@ struct StagedUploadsCreatePayload {
 }
 
 impl Shopify {
+    /// Runs every query as a bulk operation, keeping at most
+    /// `options.max_concurrent` operations in flight.
+    ///
+    /// Queued queries start as soon as a running operation reaches a terminal
+    /// status. The returned vector has one entry per input, in input order: the
+    /// terminal operation, or the error that prevented it from starting or
+    /// being polled.
     pub async fn run_bulk_queries<I, S>(
         &self,
         queries: I,
         options: BulkConcurrencyOptions,
-    ) -> Result<Vec<BulkOperationPayload>, ShopifyAPIError>
+    ) -> Vec<Result<ShopifyBulkOperation, ShopifyAPIError>>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<str>,
     {
-        let queries = queries.into_iter().collect::<Vec<_>>();
-        if queries.len() > options.max_concurrent {
-            return Err(ShopifyAPIError::Other(format!(
-                "received {} bulk queries but max_concurrent is {}",
-                queries.len(),
-                options.max_concurrent
-            )));
-        }
+        let wait = &options.wait;
+        let operations = queries.into_iter().map(|query| async move {
+            let payload = self.run_bulk_query(query.as_ref()).await?;
+            self.wait_for_started_bulk("bulkOperationRunQuery", payload, wait.clone())
+                .await
+        });
 
-        let mut operations = Vec::with_capacity(queries.len());
-        for query in queries {
-            operations.push(self.run_bulk_query(query.as_ref()).await?);
-        }
-
-        Ok(operations)
+        schedule_bulk(operations, options.max_concurrent).await
     }
 
+    /// Runs every `(mutation, staged_upload_path)` pair as a bulk operation,
+    /// with the same scheduling and per-input results as
+    /// [`Shopify::run_bulk_queries`].
     pub async fn run_bulk_mutations<I, M, P>(
         &self,
         mutations: I,
         options: BulkConcurrencyOptions,
-    ) -> Result<Vec<BulkOperationPayload>, ShopifyAPIError>
+    ) -> Vec<Result<ShopifyBulkOperation, ShopifyAPIError>>
     where
         I: IntoIterator<Item = (M, P)>,
         M: AsRef<str>,
         P: AsRef<str>,
     {
-        let mutations = mutations.into_iter().collect::<Vec<_>>();
-        if mutations.len() > options.max_concurrent {
+        let wait = &options.wait;
+        let operations = mutations
+            .into_iter()
+            .map(|(mutation, staged_upload_path)| async move {
+                let payload = self
+                    .run_bulk_mutation(mutation.as_ref(), staged_upload_path.as_ref())
+                    .await?;
+                self.wait_for_started_bulk("bulkOperationRunMutation", payload, wait.clone())
+                    .await
+            });
+
+        schedule_bulk(operations, options.max_concurrent).await
+    }
+
+    async fn wait_for_started_bulk(
+        &self,
+        mutation_name: &str,
+        payload: BulkOperationPayload,
+        options: BulkWaitOptions,
+    ) -> Result<ShopifyBulkOperation, ShopifyAPIError> {
+        if !payload.user_errors.is_empty() {
             return Err(ShopifyAPIError::Other(format!(
-                "received {} bulk mutations but max_concurrent is {}",
-                mutations.len(),
-                options.max_concurrent
+                "{mutation_name} returned errors: {:?}",
+                payload.user_errors
             )));
         }
 
-        let mut operations = Vec::with_capacity(mutations.len());
-        for (mutation, staged_upload_path) in mutations {
-            operations.push(
-                self.run_bulk_mutation(mutation.as_ref(), staged_upload_path.as_ref())
-                    .await?,
-            );
-        }
+        let operation = payload.bulk_operation.ok_or_else(|| {
+            ShopifyAPIError::Other(format!("{mutation_name} returned no bulk operation"))
+        })?;
 
-        Ok(operations)
+        self.wait_for_bulk(&operation.id, options).await
     }
 
     pub async fn run_bulk_query(
@@ -404,17 +430,84 @@ impl Shopify {
         Ok(data.bulk_operations)
     }
 
+    /// Streams every bulk operation matching `filter`, following `pageInfo`.
+    /// `filter.first` is used as the page size.
+    pub fn bulk_operations_stream(
+        &self,
+        filter: BulkOperationsFilter,
+    ) -> impl Stream<Item = Result<ShopifyBulkOperation, ShopifyAPIError>> + Send + 'static {
+        let first = if filter.first == 0 { 10 } else { filter.first };
+        self.paginate(
+            r#"
+            query bulkOperations($first: Int!, $query: String, $after: String) {
+                bulkOperations(first: $first, query: $query, after: $after) {
+                    nodes {
+                        id
+                        status
+                        errorCode
+                        createdAt
+                        completedAt
+                        objectCount
+                        fileSize
+                        url
+                        partialDataUrl
+                    }
+                    pageInfo {
+                        hasNextPage
+                        endCursor
+                    }
+                }
+            }
+            "#,
+            &json!({
+                "query": filter.query,
+                "after": filter.after,
+            }),
+            &[ReadJsonTreeSteps::Key("bulkOperations")],
+            PaginateOptions {
+                page_size: Some(first),
+                ..PaginateOptions::default()
+            },
+        )
+    }
+
     pub async fn wait_for_bulk(
         &self,
         id: &str,
         options: BulkWaitOptions,
     ) -> Result<ShopifyBulkOperation, ShopifyAPIError> {
+        let span = telemetry::Span::bulk_wait(&self.shop_domain, id);
         let started_at = Instant::now();
+        let result = span
+            .instrument(self.poll_bulk(id, options, started_at, &span))
+            .await;
+        span.record_result(&result, started_at);
+
+        if let (Some(metrics), Ok(operation)) = (self.metrics(), &result) {
+            let labels = MetricLabels {
+                shop: &self.shop_domain,
+                operation: "bulk_operation",
+            };
+            let duration = operation.duration().unwrap_or_else(|| started_at.elapsed());
+            metrics.bulk_operation(&labels, &operation.status, duration);
+        }
+        result
+    }
 
+    async fn poll_bulk(
+        &self,
+        id: &str,
+        options: BulkWaitOptions,
+        started_at: Instant,
+        span: &telemetry::Span,
+    ) -> Result<ShopifyBulkOperation, ShopifyAPIError> {
+        let mut polls = 0;
         loop {
             let operation = self.get_bulk_operation(id).await?.ok_or_else(|| {
                 ShopifyAPIError::Other(format!("bulk operation `{id}` was not found"))
             })?;
+            polls += 1;
+            span.record_bulk_poll(polls, &operation.status);
 
             if operation.status.is_terminal() {
                 return Ok(operation);
@@ -432,6 +525,12 @@ impl Shopify {
         }
     }
 
+    async fn get_with_retry(&self, url: &str) -> Result<HttpResponse, ShopifyAPIError> {
+        self.retry_policy()
+            .run(|| async { error_for_status(self.send(HttpRequest::get(url)).await?).await })
+            .await
+    }
+
     pub async fn stage_upload_jsonl<T>(&self, data: &[T]) -> Result<String, ShopifyAPIError>
     where
         T: Serialize,
@@ -446,28 +545,28 @@ impl Shopify {
             .create_staged_upload("bulk_op_vars", "text/jsonl")
             .await?;
 
-        let mut staged_upload_path = String::new();
-        let mut form = reqwest::multipart::Form::new();
-
-        for parameter in target.parameters {
-            if parameter.name == "key" {
-                staged_upload_path = parameter.value.clone();
-            }
-            form = form.text(parameter.name, parameter.value);
-        }
-
-        let file_part = reqwest::multipart::Part::bytes(jsonl_data.into_bytes())
-            .file_name("bulk_op_vars")
-            .mime_str("text/jsonl")
-            .map_err(|err| ShopifyAPIError::Other(err.to_string()))?;
+        let staged_upload_path = target
+            .parameters
+            .iter()
+            .find(|parameter| parameter.name == "key")
+            .map(|parameter| parameter.value.clone())
+            .unwrap_or_default();
+        let form = target
+            .parameters
+            .iter()
+            .fold(MultipartForm::new(), |form, parameter| {
+                form.text(parameter.name.clone(), parameter.value.clone())
+            })
+            .file("file", "bulk_op_vars", "text/jsonl", jsonl_data);
 
-        form = form.part("file", file_part);
-        self.client()
-            .post(&target.url)
-            .multipart(form)
-            .send()
-            .await?
-            .error_for_status()?;
+        self.retry_policy()
+            .run(|| async {
+                let response = self
+                    .send(HttpRequest::post(&target.url).multipart(form.clone()))
+                    .await?;
+                error_for_status(response).await.map(drop)
+            })
+            .await?;
 
         if staged_upload_path.is_empty() {
             return Err(ShopifyAPIError::Other(
@@ -478,14 +577,71 @@ impl Shopify {
         Ok(staged_upload_path)
     }
 
+    /// Downloads a bulk operation result file and parses every JSONL line.
+    /// The request goes through this client's transport, retry policy and
+    /// middleware.
+    pub async fn collect_bulk_jsonl<T>(&self, url: &str) -> Result<Vec<T>, ShopifyAPIError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let body = self.get_with_retry(url).await?.text().await?;
+        parse_jsonl(&body)
+    }
+
+    #[deprecated(
+        note = "sends through a default reqwest client, bypassing the transport, retry policy and middleware; use `Shopify::collect_bulk_jsonl`"
+    )]
     pub async fn download_bulk_jsonl<T>(url: &str) -> Result<Vec<T>, ShopifyAPIError>
     where
         T: serde::de::DeserializeOwned,
     {
-        let body = reqwest::get(url).await?.error_for_status()?.text().await?;
+        let body = RetryPolicy::default()
+            .run(|| async {
+                let response = ReqwestTransport::default()
+                    .send(HttpRequest::get(url))
+                    .await?;
+                error_for_status(response).await?.text().await
+            })
+            .await?;
         parse_jsonl(&body)
     }
 
+    /// Streams a bulk operation result file, yielding one typed item per JSONL
+    /// line without buffering the whole body in memory.
+    pub async fn stream_bulk_jsonl<T>(
+        &self,
+        url: &str,
+    ) -> Result<impl Stream<Item = Result<T, ShopifyAPIError>> + Send + 'static, ShopifyAPIError>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+    {
+        let response = self.get_with_retry(url).await?;
+
+        Ok(parse_jsonl_stream(response.bytes_stream()))
+    }
+
+    /// Writes a bulk operation result file to `path` chunk by chunk and
+    /// returns the number of bytes written.
+    pub async fn download_bulk_jsonl_to_file(
+        &self,
+        url: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<u64, ShopifyAPIError> {
+        let response = self.get_with_retry(url).await?;
+        let mut body = response.bytes_stream();
+        let mut file = tokio::fs::File::create(path).await?;
+        let mut written = 0;
+
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+
+        file.flush().await?;
+        Ok(written)
+    }
+
     async fn create_staged_upload(
         &self,
         filename: &str,
@@ -538,16 +694,333 @@ impl Shopify {
     }
 }
 
+/// Drives at most `max_concurrent` operations at once and returns their
+/// results in input order, whatever order they finish in.
+async fn schedule_bulk<I, F, T>(operations: I, max_concurrent: usize) -> Vec<T>
+where
+    I: IntoIterator<Item = F>,
+    F: Future<Output = T>,
+{
+    let mut results = stream::iter(operations.into_iter().enumerate())
+        .map(|(index, operation)| async move { (index, operation.await) })
+        .buffer_unordered(max_concurrent.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
 pub fn parse_jsonl<T>(body: &str) -> Result<Vec<T>, ShopifyAPIError>
 where
     T: serde::de::DeserializeOwned,
 {
     body.lines()
-        .filter(|line| !line.trim().is_empty())
-        .map(|line| serde_json::from_str(line).map_err(ShopifyAPIError::JsonParseError))
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| parse_jsonl_line(line.as_bytes(), index + 1))
         .collect()
 }
 
+/// Turns a stream of body chunks into a stream of typed JSONL items.
+///
+/// Only the current, incomplete line is kept in memory. Parse errors carry the
+/// 1-based line number; the stream ends after a transport error.
+pub fn parse_jsonl_stream<S, B, T>(body: S) -> impl Stream<Item = Result<T, ShopifyAPIError>>
+where
+    S: Stream<Item = Result<B, ShopifyAPIError>> + Unpin,
+    B: AsRef<[u8]>,
+    T: serde::de::DeserializeOwned,
+{
+    let state = JsonlStreamState {
+        body,
+        buffer: Vec::new(),
+        start: 0,
+        scanned: 0,
+        line: 0,
+        finished: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(offset) = state.buffer[state.scanned..]
+                .iter()
+                .position(|byte| *byte == b'\n')
+            {
+                let end = state.scanned + offset;
+                let line = &state.buffer[state.start..end];
+                state.line += 1;
+                let item = (!is_blank(line)).then(|| parse_jsonl_line(line, state.line));
+                state.start = end + 1;
+                state.scanned = state.start;
+                match item {
+                    Some(item) => return Some((item, state)),
+                    None => continue,
+                }
+            }
+            state.scanned = state.buffer.len();
+
+            if state.finished {
+                let line = &state.buffer[state.start..];
+                if is_blank(line) {
+                    return None;
+                }
+                state.line += 1;
+                let item = parse_jsonl_line(line, state.line);
+                state.buffer.clear();
+                state.start = 0;
+                state.scanned = 0;
+                return Some((item, state));
+            }
+
+            // Parsed lines are dropped once per chunk rather than once per
+            // line, which would shift the rest of the buffer every time.
+            state.buffer.drain(..state.start);
+            state.scanned -= state.start;
+            state.start = 0;
+            match state.body.next().await {
+                Some(Ok(chunk)) => state.buffer.extend_from_slice(chunk.as_ref()),
+                Some(Err(err)) => {
+                    state.finished = true;
+                    state.buffer.clear();
+                    state.scanned = 0;
+                    return Some((Err(err), state));
+                }
+                None => state.finished = true,
+            }
+        }
+    })
+}
+
+struct JsonlStreamState<S> {
+    body: S,
+    buffer: Vec<u8>,
+    /// Where the current line starts in `buffer`.
+    start: usize,
+    /// How far `buffer` has been searched for a newline.
+    scanned: usize,
+    line: usize,
+    finished: bool,
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(u8::is_ascii_whitespace)
+}
+
+fn parse_jsonl_line<T>(line: &[u8], line_number: usize) -> Result<T, ShopifyAPIError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    serde_json::from_slice(line).map_err(|source| ShopifyAPIError::JsonlParseError {
+        line: line_number,
+        source,
+    })
+}
+
+/// A bulk operation row together with the rows that reference it through
+/// `__parentId`.
+///
+/// Children are grouped by the resource type of their GID (`ProductVariant`,
+/// `Metafield`, ...), falling back to `__typename` and then to an empty key
+/// for rows without either.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkNode {
+    pub id: Option<String>,
+    pub resource_type: Option<String>,
+    pub data: serde_json::Value,
+    pub children: BTreeMap<String, Vec<BulkNode>>,
+}
+
+impl BulkNode {
+    fn from_row(mut data: serde_json::Value) -> (Self, Option<String>) {
+        let parent_id = data
+            .as_object_mut()
+            .and_then(|object| object.remove("__parentId"))
+            .and_then(|value| value.as_str().map(str::to_string));
+        let id = data
+            .get("id")
+            .and_then(|id| id.as_str())
+            .map(str::to_string);
+        let resource_type = id
+            .as_deref()
+            .and_then(gid_resource_type)
+            .map(str::to_string);
+
+        let node = Self {
+            id,
+            resource_type,
+            data,
+            children: BTreeMap::new(),
+        };
+        (node, parent_id)
+    }
+
+    fn group_key(&self) -> String {
+        self.resource_type
+            .clone()
+            .or_else(|| {
+                self.data
+                    .get("__typename")
+                    .and_then(|value| value.as_str())
+                    .map(str::to_string)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the children of the given resource type, e.g. `"ProductVariant"`.
+    pub fn children_of(&self, resource_type: &str) -> &[BulkNode] {
+        self.children
+            .get(resource_type)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Deserializes this row's own fields, without its children.
+    pub fn deserialize<T>(&self) -> Result<T, ShopifyAPIError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        serde_json::from_value(self.data.clone()).map_err(ShopifyAPIError::JsonParseError)
+    }
+}
+
+/// Extracts the resource type from a Shopify GID, e.g. `Product` from
+/// `gid://shopify/Product/123`.
+pub fn gid_resource_type(gid: &str) -> Option<&str> {
+    let mut parts = gid.strip_prefix("gid://")?.split('/');
+    let _namespace = parts.next()?;
+    parts
+        .next()
+        .filter(|resource_type| !resource_type.is_empty())
+}
+
+/// Rebuilds the parent/child tree from flattened bulk operation rows.
+///
+/// Parents must appear before their children, which Shopify guarantees for
+/// bulk query output. Children do not need to follow their parent directly.
+pub fn reassemble_bulk_rows<I>(rows: I) -> Result<Vec<BulkNode>, ShopifyAPIError>
+where
+    I: IntoIterator<Item = serde_json::Value>,
+{
+    let mut builder = BulkTreeBuilder::default();
+    for row in rows {
+        builder.push(row)?;
+    }
+
+    Ok(builder.roots)
+}
+
+/// Streaming counterpart of [`reassemble_bulk_rows`]: yields each top-level
+/// row with its descendants as soon as the next top-level row starts.
+///
+/// This needs every child to follow its parent before the next top-level row,
+/// which Shopify only guarantees for operations started with
+/// [`Shopify::run_bulk_query_with_grouping`] and `group_objects: true`.
+pub fn reassemble_bulk_stream<S>(rows: S) -> impl Stream<Item = Result<BulkNode, ShopifyAPIError>>
+where
+    S: Stream<Item = Result<serde_json::Value, ShopifyAPIError>> + Unpin,
+{
+    let state = (rows, BulkTreeBuilder::default(), false);
+
+    stream::unfold(state, |(mut rows, mut builder, finished)| async move {
+        if finished {
+            return None;
+        }
+
+        loop {
+            match rows.next().await {
+                Some(Ok(row)) => {
+                    let starts_root = row.get("__parentId").is_none();
+                    let completed = if starts_root {
+                        builder.take_root()
+                    } else {
+                        None
+                    };
+                    if let Err(err) = builder.push(row) {
+                        return Some((Err(err), (rows, builder, true)));
+                    }
+                    if let Some(root) = completed {
+                        return Some((Ok(root), (rows, builder, false)));
+                    }
+                }
+                Some(Err(err)) => return Some((Err(err), (rows, builder, true))),
+                None => {
+                    let root = builder.take_root()?;
+                    return Some((Ok(root), (rows, builder, true)));
+                }
+            }
+        }
+    })
+}
+
+#[derive(Debug, Default)]
+struct BulkTreeBuilder {
+    roots: Vec<BulkNode>,
+    paths: HashMap<String, BulkNodePath>,
+}
+
+#[derive(Debug, Clone)]
+struct BulkNodePath {
+    root: usize,
+    children: Vec<(String, usize)>,
+}
+
+impl BulkTreeBuilder {
+    fn push(&mut self, row: serde_json::Value) -> Result<(), ShopifyAPIError> {
+        let (node, parent_id) = BulkNode::from_row(row);
+        let id = node.id.clone();
+
+        let path = match parent_id {
+            None => {
+                self.roots.push(node);
+                BulkNodePath {
+                    root: self.roots.len() - 1,
+                    children: Vec::new(),
+                }
+            }
+            Some(parent_id) => {
+                let mut path = self.paths.get(&parent_id).cloned().ok_or_else(|| {
+                    ShopifyAPIError::Other(format!(
+                        "bulk row references unknown parent `{parent_id}`"
+                    ))
+                })?;
+                let key = node.group_key();
+                let siblings = self
+                    .node_mut(&path)
+                    .children
+                    .entry(key.clone())
+                    .or_default();
+                siblings.push(node);
+                path.children.push((key, siblings.len() - 1));
+                path
+            }
+        };
+
+        if let Some(id) = id {
+            self.paths.insert(id, path);
+        }
+        Ok(())
+    }
+
+    fn node_mut(&mut self, path: &BulkNodePath) -> &mut BulkNode {
+        let mut node = &mut self.roots[path.root];
+        for (key, index) in &path.children {
+            node = &mut node
+                .children
+                .get_mut(key)
+                .expect("bulk node paths only point at inserted children")[*index];
+        }
+        node
+    }
+
+    fn take_root(&mut self) -> Option<BulkNode> {
+        self.paths.clear();
+        let root = self.roots.pop();
+        self.roots.clear();
+        root
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -559,4 +1032,118 @@ mod tests {
         assert_eq!(parsed.len(), 2);
         assert_eq!(parsed[0]["id"], 1);
     }
+
+    #[tokio::test]
+    async fn streams_jsonl_across_chunk_boundaries() {
+        let chunks = vec![
+            Ok::<_, ShopifyAPIError>("{\"id\":1}\n{\"i".as_bytes()),
+            Ok("d\":2}\n\n{\"id\":".as_bytes()),
+            Ok("3}".as_bytes()),
+        ];
+
+        let parsed: Vec<serde_json::Value> = parse_jsonl_stream(stream::iter(chunks))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[2]["id"], 3);
+    }
+
+    #[tokio::test]
+    async fn streams_many_jsonl_lines_from_one_chunk() {
+        let body = (0..10_000)
+            .map(|id| format!("{{\"id\":{id}}}\n"))
+            .collect::<String>();
+        let chunks = vec![Ok::<_, ShopifyAPIError>(body.as_bytes())];
+
+        let parsed: Vec<serde_json::Value> = parse_jsonl_stream(stream::iter(chunks))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(parsed.len(), 10_000);
+        assert_eq!(parsed[9_999]["id"], 9_999);
+    }
+
+    #[tokio::test]
+    async fn jsonl_stream_reports_failing_line_number() {
+        let chunks = vec![Ok::<_, ShopifyAPIError>(
+            "{\"id\":1}\n\nnot json\n".as_bytes(),
+        )];
+
+        let parsed: Vec<Result<serde_json::Value, _>> =
+            parse_jsonl_stream(stream::iter(chunks)).collect().await;
+
+        assert!(parsed[0].is_ok());
+        assert!(matches!(
+            parsed[1],
+            Err(ShopifyAPIError::JsonlParseError { line: 3, .. })
+        ));
+    }
+
+    fn product_rows() -> Vec<serde_json::Value> {
+        vec![
+            json!({"id": "gid://shopify/Product/1", "title": "Shirt"}),
+            json!({"id": "gid://shopify/ProductVariant/10", "__parentId": "gid://shopify/Product/1"}),
+            json!({"id": "gid://shopify/Metafield/100", "__parentId": "gid://shopify/ProductVariant/10"}),
+            json!({"id": "gid://shopify/Metafield/11", "__parentId": "gid://shopify/Product/1"}),
+            json!({"id": "gid://shopify/Product/2", "title": "Hat"}),
+            json!({"id": "gid://shopify/ProductVariant/20", "__parentId": "gid://shopify/Product/2"}),
+        ]
+    }
+
+    #[test]
+    fn reassembles_nested_bulk_rows_by_resource_type() {
+        let mut rows = product_rows();
+        let late_child = rows.remove(3);
+        rows.push(late_child);
+
+        let products = reassemble_bulk_rows(rows).unwrap();
+
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].resource_type.as_deref(), Some("Product"));
+        let variants = products[0].children_of("ProductVariant");
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].children_of("Metafield").len(), 1);
+        assert_eq!(products[0].children_of("Metafield").len(), 1);
+        assert!(variants[0].data.get("__parentId").is_none());
+        assert!(reassemble_bulk_rows(vec![json!({"id": "x", "__parentId": "missing"})]).is_err());
+    }
+
+    #[tokio::test]
+    async fn reassembles_grouped_bulk_rows_as_a_stream() {
+        let rows = stream::iter(product_rows().into_iter().map(Ok));
+
+        let products: Vec<BulkNode> = reassemble_bulk_stream(rows)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(products, reassemble_bulk_rows(product_rows()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn bulk_scheduler_caps_concurrency_and_keeps_input_order() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let operations = (0..12u64).map(|index| {
+            let running = &running;
+            let peak = &peak;
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(12 - index)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                index
+            }
+        });
+
+        let results = schedule_bulk(operations, 5).await;
+
+        assert_eq!(results, (0..12).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 5);
+    }
 }
@@ -1,11 +1,21 @@
 mod bulk_query;
+mod pagination;
 pub mod types;
 
+use std::time::Instant;
+
 use serde::{Deserialize, Serialize};
 
-use crate::{utils::ReadJsonTreeSteps, Shopify, ShopifyAPIError};
+use crate::{
+    telemetry,
+    throttle::ThrottleWait,
+    transport::HttpRequest,
+    utils::{error_for_status, ReadJsonTreeSteps},
+    GraphqlRequest, MetricLabels, Shopify, ShopifyAPIError,
+};
 
 pub use bulk_query::*;
+pub use pagination::*;
 
 #[cfg(feature = "graphql-client")]
 use graphql_client::{GraphQLQuery, Response as GraphQLClientResponse};
@@ -25,13 +35,112 @@ pub struct GraphqlError {
     pub extensions: Option<serde_json::Value>,
 }
 
+/// `extensions.cost` as reported by the Admin GraphQL API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryCost {
+    pub requested_query_cost: f64,
+    pub actual_query_cost: Option<f64>,
+    pub throttle_status: ThrottleStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThrottleStatus {
+    pub maximum_available: f64,
+    pub currently_available: f64,
+    pub restore_rate: f64,
+}
+
+/// Typed GraphQL data together with the response metadata.
+#[derive(Debug, Clone)]
+pub struct GraphqlWithMeta<T> {
+    pub data: T,
+    pub cost: Option<QueryCost>,
+    pub extensions: Option<serde_json::Value>,
+}
+
 #[derive(Debug, Serialize)]
-struct GraphqlRequest<'a, Variables> {
+struct GraphqlBody<'a, Variables> {
     query: &'a str,
     variables: &'a Variables,
 }
 
+impl<T> GraphqlResponse<T> {
+    /// Parses `extensions.cost`, if Shopify reported it.
+    pub fn cost(&self) -> Option<QueryCost> {
+        self.extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("cost"))
+            .and_then(|cost| serde_json::from_value(cost.clone()).ok())
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.errors.as_ref().is_some_and(|errors| {
+            errors.iter().any(|error| {
+                error
+                    .extensions
+                    .as_ref()
+                    .and_then(|v| v.get("code"))
+                    .and_then(|v| v.as_str())
+                    == Some("THROTTLED")
+            })
+        })
+    }
+}
+
+impl GraphqlResponse<serde_json::Value> {
+    /// Turns throttling and GraphQL `errors` into errors and deserializes
+    /// `data`.
+    pub(crate) fn into_data<T>(self) -> Result<GraphqlWithMeta<T>, ShopifyAPIError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        if self.is_throttled() {
+            return Err(ShopifyAPIError::Throttled);
+        }
+        let cost = self.cost();
+        if let Some(errors) = self.errors {
+            return Err(ShopifyAPIError::GraphqlErrors(errors));
+        }
+
+        let data = self.data.ok_or(ShopifyAPIError::MissingGraphqlData)?;
+        Ok(GraphqlWithMeta {
+            data: serde_json::from_value(data).map_err(ShopifyAPIError::JsonParseError)?,
+            cost,
+            extensions: self.extensions,
+        })
+    }
+}
+
+impl GraphqlWithMeta<serde_json::Value> {
+    /// Deserializes the value found at `json_finder` in `data`.
+    pub(crate) fn at_path<T>(
+        self,
+        json_finder: &[ReadJsonTreeSteps<'_>],
+    ) -> Result<GraphqlWithMeta<T>, ShopifyAPIError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let value = crate::utils::read_json_tree(&self.data, json_finder)
+            .map_err(|_| ShopifyAPIError::NotWantedJsonFormat(self.data.to_string()))?;
+        Ok(GraphqlWithMeta {
+            data: serde_json::from_value(value.to_owned())
+                .map_err(ShopifyAPIError::JsonParseError)?,
+            cost: self.cost,
+            extensions: self.extensions,
+        })
+    }
+}
+
 impl Shopify {
+    /// Sends a GraphQL request and returns the raw response.
+    ///
+    /// With [`crate::ShopifyConfig::throttle`] set, the request first waits
+    /// for the shared cost bucket, and `THROTTLED` responses are replayed once
+    /// the bucket has restored enough points. Without it, `THROTTLED`
+    /// responses are retried by [`crate::ShopifyConfig::retry`] and fail with
+    /// [`ShopifyAPIError::Throttled`] once it gives up.
     pub async fn graphql_raw<Variables>(
         &self,
         query: &str,
@@ -40,21 +149,119 @@ impl Shopify {
     where
         Variables: serde::Serialize,
     {
-        let token = self.access_token().await?;
-        let response = self
-            .client()
-            .post(self.get_query_url())
-            .header("Content-Type", "application/json")
-            .header("X-Shopify-Access-Token", token)
-            .json(&GraphqlRequest { query, variables })
-            .send()
-            .await?
-            .error_for_status()?;
+        if self.middleware.is_empty() {
+            self.observed_graphql(query, variables).await
+        } else {
+            self.graphql_through_middleware(GraphqlRequest::new(query, variables)?)
+                .await
+        }
+    }
+
+    /// Sends a query with its tracing span and metrics.
+    pub(crate) async fn observed_graphql<Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<GraphqlResponse<serde_json::Value>, ShopifyAPIError>
+    where
+        Variables: serde::Serialize,
+    {
+        let operation = telemetry::operation_name(query);
+        let labels = MetricLabels {
+            shop: &self.shop_domain,
+            operation: operation.as_deref().unwrap_or("anonymous"),
+        };
+        let span = telemetry::Span::graphql(
+            &self.shop_domain,
+            self.api_version.as_str(),
+            operation.as_deref(),
+            query,
+        );
+        let started_at = Instant::now();
+        let result = span
+            .instrument(self.throttled_graphql(query, variables, &labels))
+            .await;
+        let cost = result.as_ref().ok().and_then(GraphqlResponse::cost);
+        span.record_cost(cost.as_ref());
+        span.record_result(&result, started_at);
+
+        if let Some(metrics) = self.metrics() {
+            if let Some(cost) = &cost {
+                metrics.query_cost(&labels, cost);
+            }
+            // GraphQL errors arrive with a 200 and only become errors in
+            // `graphql_with_meta`, but still count as failed requests.
+            let response_error = match &result {
+                Ok(response) if response.is_throttled() => Some(ShopifyAPIError::Throttled),
+                Ok(response) => response.errors.clone().map(ShopifyAPIError::GraphqlErrors),
+                Err(_) => None,
+            };
+            let error = result.as_ref().err().or(response_error.as_ref());
+            metrics.request(&labels, started_at.elapsed(), error);
+        }
+        result
+    }
+
+    async fn throttled_graphql<Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+        labels: &MetricLabels<'_>,
+    ) -> Result<GraphqlResponse<serde_json::Value>, ShopifyAPIError>
+    where
+        Variables: serde::Serialize,
+    {
+        let Some(limiter) = &self.throttle else {
+            return self.send_graphql(query, variables).await;
+        };
 
-        let status = response.status();
-        let body = response.text().await?;
-        log::debug!("shopify graphql response status: {status}");
-        serde_json::from_str(&body).map_err(ShopifyAPIError::JsonParseError)
+        let metrics = self.metrics();
+        let on_wait = |wait| {
+            let wait = match wait {
+                ThrottleWait::Bucket(waited) => waited,
+                ThrottleWait::Throttled { retries, wait } => {
+                    telemetry::Span::current().record_throttled_retries(retries);
+                    wait
+                }
+            };
+            if let Some(metrics) = metrics {
+                metrics.throttle_wait(labels, wait);
+            }
+        };
+        limiter
+            .run(query, on_wait, || self.send_graphql(query, variables))
+            .await
+    }
+
+    async fn send_graphql<Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<GraphqlResponse<serde_json::Value>, ShopifyAPIError>
+    where
+        Variables: serde::Serialize,
+    {
+        let request = GraphqlBody { query, variables };
+        self.retry_policy_for(query)
+            .run(|| async {
+                let response = self
+                    .send_authorized(HttpRequest::post(self.get_query_url()).json(&request)?)
+                    .await?;
+                telemetry::Span::current().record_status(response.status());
+                let response = error_for_status(response).await?;
+
+                let status = response.status();
+                let body = response.text().await?;
+                log::debug!("shopify graphql response status: {status}");
+                let response: GraphqlResponse<serde_json::Value> =
+                    serde_json::from_str(&body).map_err(ShopifyAPIError::JsonParseError)?;
+                // Without a limiter, `THROTTLED` is retried like a 429.
+                if self.throttle.is_none() && response.is_throttled() {
+                    return Err(ShopifyAPIError::Throttled);
+                }
+                Ok(response)
+            })
+            .await
     }
 
     pub async fn graphql<ReturnType, Variables>(
@@ -66,23 +273,21 @@ impl Shopify {
         ReturnType: serde::de::DeserializeOwned,
         Variables: serde::Serialize,
     {
-        let response = self.graphql_raw(query, variables).await?;
-        if let Some(errors) = response.errors {
-            if errors.iter().any(|error| {
-                error
-                    .extensions
-                    .as_ref()
-                    .and_then(|v| v.get("code"))
-                    .and_then(|v| v.as_str())
-                    == Some("THROTTLED")
-            }) {
-                return Err(ShopifyAPIError::Throttled);
-            }
-            return Err(ShopifyAPIError::GraphqlErrors(errors));
-        }
+        Ok(self.graphql_with_meta(query, variables).await?.data)
+    }
 
-        let data = response.data.ok_or(ShopifyAPIError::MissingGraphqlData)?;
-        serde_json::from_value(data).map_err(ShopifyAPIError::JsonParseError)
+    /// Like [`Shopify::graphql`], but also returns the query cost and raw
+    /// extensions.
+    pub async fn graphql_with_meta<ReturnType, Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<GraphqlWithMeta<ReturnType>, ShopifyAPIError>
+    where
+        ReturnType: serde::de::DeserializeOwned,
+        Variables: serde::Serialize,
+    {
+        self.graphql_raw(query, variables).await?.into_data()
     }
 
     pub async fn graphql_at_path<ReturnType, Variables>(
@@ -95,12 +300,27 @@ impl Shopify {
         ReturnType: serde::de::DeserializeOwned,
         Variables: serde::Serialize,
     {
-        let data = self
-            .graphql::<serde_json::Value, _>(query, variables)
-            .await?;
-        let value = crate::utils::read_json_tree(&data, json_finder)
-            .map_err(|_| ShopifyAPIError::NotWantedJsonFormat(data.to_string()))?;
-        serde_json::from_value(value.to_owned()).map_err(ShopifyAPIError::JsonParseError)
+        Ok(self
+            .graphql_at_path_with_meta(query, variables, json_finder)
+            .await?
+            .data)
+    }
+
+    /// Like [`Shopify::graphql_at_path`], but also returns the query cost and
+    /// raw extensions.
+    pub async fn graphql_at_path_with_meta<ReturnType, Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+        json_finder: &[ReadJsonTreeSteps<'_>],
+    ) -> Result<GraphqlWithMeta<ReturnType>, ShopifyAPIError>
+    where
+        ReturnType: serde::de::DeserializeOwned,
+        Variables: serde::Serialize,
+    {
+        self.graphql_with_meta::<serde_json::Value, _>(query, variables)
+            .await?
+            .at_path(json_finder)
     }
 
     #[cfg(feature = "graphql-client")]
@@ -108,22 +328,19 @@ impl Shopify {
         &self,
         variables: Q::Variables,
     ) -> Result<GraphQLClientResponse<Q::ResponseData>, ShopifyAPIError> {
-        let token = self.access_token().await?;
         let body = Q::build_query(variables);
-        let response = self
-            .client()
-            .post(self.get_query_url())
-            .header("Content-Type", "application/json")
-            .header("X-Shopify-Access-Token", token)
-            .json(&body)
-            .send()
-            .await?
-            .error_for_status()?;
+        self.retry_policy_for(body.query)
+            .run(|| async {
+                let response = self
+                    .send_authorized(HttpRequest::post(self.get_query_url()).json(&body)?)
+                    .await?;
+                let response = error_for_status(response).await?;
 
-        response
-            .json::<GraphQLClientResponse<Q::ResponseData>>()
+                response
+                    .json::<GraphQLClientResponse<Q::ResponseData>>()
+                    .await
+            })
             .await
-            .map_err(ShopifyAPIError::ConnectionFailed)
     }
 }
 
@@ -135,11 +352,24 @@ mod tests {
     fn graphql_response_parses_errors_and_extensions() {
         let raw = r#"{
             "errors": [{"message": "boom", "extensions": {"code": "THROTTLED"}}],
-            "extensions": {"cost": {"actualQueryCost": 1}}
+            "extensions": {"cost": {
+                "requestedQueryCost": 12,
+                "actualQueryCost": 10,
+                "throttleStatus": {
+                    "maximumAvailable": 2000.0,
+                    "currentlyAvailable": 1990,
+                    "restoreRate": 100.0
+                }
+            }}
         }"#;
 
         let parsed: GraphqlResponse<serde_json::Value> = serde_json::from_str(raw).unwrap();
 
+        let cost = parsed.cost().unwrap();
+        assert_eq!(cost.requested_query_cost, 12.0);
+        assert_eq!(cost.actual_query_cost, Some(10.0));
+        assert_eq!(cost.throttle_status.currently_available, 1990.0);
+        assert!(parsed.is_throttled());
         assert_eq!(parsed.errors.unwrap()[0].message, "boom");
         assert!(parsed.extensions.unwrap().get("cost").is_some());
     }
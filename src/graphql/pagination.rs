@@ -0,0 +1,290 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use futures_util::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::{
+    utils::{read_json_tree, ReadJsonTreeSteps},
+    Shopify, ShopifyAPIError,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Connection<T> {
+    pub edges: Vec<Edge<T>>,
+    #[serde(rename = "pageInfo")]
+    pub page_info: PageInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Edge<T> {
+    pub cursor: String,
+    pub node: T,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PageInfo {
+    #[serde(rename = "hasNextPage")]
+    pub has_next_page: bool,
+    #[serde(rename = "endCursor")]
+    pub end_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PaginateOptions {
+    /// Sets the `$first` variable of every page request.
+    pub page_size: Option<u32>,
+    /// Requests the next page in the background as soon as the current one
+    /// arrives, instead of when it has been consumed.
+    pub prefetch: bool,
+}
+
+#[derive(Debug, Clone)]
+enum PathStep {
+    Key(String),
+    Index(usize),
+}
+
+struct Page {
+    nodes: Vec<serde_json::Value>,
+    page_info: PageInfo,
+}
+
+enum NextPage {
+    Cursor(Option<String>),
+    Pending(AbortOnDrop<Result<Page, ShopifyAPIError>>),
+    Failed(ShopifyAPIError),
+    Done,
+}
+
+/// A prefetch task that is cancelled when the stream owning it is dropped.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl<T> std::future::Future for AbortOnDrop<T> {
+    type Output = Result<T, tokio::task::JoinError>;
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        std::pin::Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+struct PaginateState {
+    shopify: Shopify,
+    query: Arc<str>,
+    variables: Arc<serde_json::Value>,
+    path: Arc<[PathStep]>,
+    options: PaginateOptions,
+    buffer: VecDeque<serde_json::Value>,
+    next: NextPage,
+    last_cursor: Option<String>,
+}
+
+impl Shopify {
+    /// Streams every node of a connection query, fetching pages lazily.
+    ///
+    /// The query must declare an `$after: String` variable (and `$first: Int`
+    /// when [`PaginateOptions::page_size`] is set) and pass it to the
+    /// connection found at `connection_path` inside `data`. Both
+    /// `edges { node }` and `nodes` selections are supported, and
+    /// `pageInfo { hasNextPage endCursor }` must be selected.
+    pub fn paginate<T, Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+        connection_path: &[ReadJsonTreeSteps<'_>],
+        options: PaginateOptions,
+    ) -> impl Stream<Item = Result<T, ShopifyAPIError>> + Send + 'static
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+        Variables: serde::Serialize,
+    {
+        let variables = serde_json::to_value(variables);
+        let path = connection_path
+            .iter()
+            .map(|step| match step {
+                ReadJsonTreeSteps::Key(key) => PathStep::Key(key.to_string()),
+                ReadJsonTreeSteps::Index(index) => PathStep::Index(*index),
+            })
+            .collect::<Vec<_>>();
+
+        let state = match variables {
+            Ok(variables) if variables.is_object() || variables.is_null() => Ok(PaginateState {
+                shopify: self.clone(),
+                query: Arc::from(query),
+                variables: Arc::new(variables),
+                path: Arc::from(path),
+                options,
+                buffer: VecDeque::new(),
+                next: NextPage::Cursor(None),
+                last_cursor: None,
+            }),
+            Ok(_) => Err(ShopifyAPIError::Other(
+                "pagination variables must serialize to a JSON object".to_string(),
+            )),
+            Err(err) => Err(ShopifyAPIError::JsonParseError(err)),
+        };
+
+        stream::unfold(Some(state), |state| async move {
+            let mut state = match state? {
+                Ok(state) => state,
+                Err(err) => return Some((Err(err), None)),
+            };
+
+            loop {
+                if let Some(node) = state.buffer.pop_front() {
+                    let item =
+                        serde_json::from_value(node).map_err(ShopifyAPIError::JsonParseError);
+                    return Some((item, Some(Ok(state))));
+                }
+
+                let page = match std::mem::replace(&mut state.next, NextPage::Done) {
+                    NextPage::Done => return None,
+                    NextPage::Failed(err) => return Some((Err(err), None)),
+                    NextPage::Cursor(after) => state.fetch(after).await,
+                    NextPage::Pending(handle) => handle
+                        .await
+                        .unwrap_or_else(|err| Err(ShopifyAPIError::Other(err.to_string()))),
+                };
+
+                let page = match page {
+                    Ok(page) => page,
+                    Err(err) => return Some((Err(err), None)),
+                };
+
+                state.buffer.extend(page.nodes);
+                state.next = match page.page_info {
+                    PageInfo {
+                        has_next_page: true,
+                        end_cursor: Some(cursor),
+                    } if state.last_cursor.as_ref() == Some(&cursor) => {
+                        NextPage::Failed(ShopifyAPIError::Other(format!(
+                            "pagination returned the same endCursor `{cursor}` twice"
+                        )))
+                    }
+                    PageInfo {
+                        has_next_page: true,
+                        end_cursor: Some(cursor),
+                    } => {
+                        state.last_cursor = Some(cursor.clone());
+                        if state.options.prefetch {
+                            NextPage::Pending(AbortOnDrop(tokio::spawn(state.fetch(Some(cursor)))))
+                        } else {
+                            NextPage::Cursor(Some(cursor))
+                        }
+                    }
+                    _ => NextPage::Done,
+                };
+            }
+        })
+    }
+}
+
+impl PaginateState {
+    fn fetch(
+        &self,
+        after: Option<String>,
+    ) -> impl std::future::Future<Output = Result<Page, ShopifyAPIError>> + Send + 'static {
+        let shopify = self.shopify.clone();
+        let query = self.query.clone();
+        let path = self.path.clone();
+        let mut variables = match self.variables.as_ref() {
+            serde_json::Value::Object(variables) => variables.clone(),
+            _ => serde_json::Map::new(),
+        };
+        // A caller-supplied `after` is the starting cursor of the first page.
+        if after.is_some() || !variables.contains_key("after") {
+            variables.insert("after".to_string(), after.into());
+        }
+        if let Some(page_size) = self.options.page_size {
+            variables.insert("first".to_string(), page_size.into());
+        }
+
+        async move {
+            let data = shopify
+                .graphql::<serde_json::Value, _>(&query, &variables)
+                .await?;
+            let steps = path
+                .iter()
+                .map(|step| match step {
+                    PathStep::Key(key) => ReadJsonTreeSteps::Key(key),
+                    PathStep::Index(index) => ReadJsonTreeSteps::Index(*index),
+                })
+                .collect::<Vec<_>>();
+            let connection = read_json_tree(&data, &steps)
+                .map_err(|_| ShopifyAPIError::NotWantedJsonFormat(data.to_string()))?;
+
+            parse_page(connection)
+        }
+    }
+}
+
+fn parse_page(connection: &serde_json::Value) -> Result<Page, ShopifyAPIError> {
+    let page_info = connection
+        .get("pageInfo")
+        .cloned()
+        .ok_or_else(|| ShopifyAPIError::NotWantedJsonFormat(connection.to_string()))?;
+    let page_info: PageInfo = serde_json::from_value(page_info)?;
+
+    let nodes = match (connection.get("nodes"), connection.get("edges")) {
+        (Some(serde_json::Value::Array(nodes)), _) => nodes.clone(),
+        (_, Some(serde_json::Value::Array(edges))) => edges
+            .iter()
+            .map(|edge| {
+                edge.get("node")
+                    .cloned()
+                    .ok_or_else(|| ShopifyAPIError::NotWantedJsonFormat(edge.to_string()))
+            })
+            .collect::<Result<_, _>>()?,
+        _ => return Err(ShopifyAPIError::NotWantedJsonFormat(connection.to_string())),
+    };
+
+    Ok(Page { nodes, page_info })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_edges_and_nodes_connections() {
+        let edges = parse_page(&json!({
+            "edges": [{"cursor": "a", "node": {"id": 1}}, {"cursor": "b", "node": {"id": 2}}],
+            "pageInfo": {"hasNextPage": true, "endCursor": "b"}
+        }))
+        .unwrap();
+        let nodes = parse_page(&json!({
+            "nodes": [{"id": 3}],
+            "pageInfo": {"hasNextPage": false, "endCursor": null}
+        }))
+        .unwrap();
+
+        assert_eq!(edges.nodes, vec![json!({"id": 1}), json!({"id": 2})]);
+        assert_eq!(edges.page_info.end_cursor.as_deref(), Some("b"));
+        assert_eq!(nodes.nodes, vec![json!({"id": 3})]);
+        assert!(!nodes.page_info.has_next_page);
+        assert!(parse_page(&json!({"edges": []})).is_err());
+    }
+
+    #[tokio::test]
+    async fn dropping_a_prefetch_cancels_its_task() {
+        let (sender, receiver) = tokio::sync::oneshot::channel::<()>();
+        let prefetch = AbortOnDrop(tokio::spawn(async move {
+            let _sender = sender;
+            std::future::pending::<()>().await;
+        }));
+
+        drop(prefetch);
+        // The sender is dropped with the cancelled task.
+        assert!(receiver.await.is_err());
+    }
+}
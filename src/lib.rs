@@ -1,20 +1,55 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{atomic::AtomicBool, Arc, Mutex};
+
+use transport::{HttpRequest, HttpResponse};
 
 use thiserror::Error;
 
 pub mod auth;
+mod builder;
+#[cfg(feature = "customer-account")]
+pub mod customer_account;
 pub mod graphql;
+pub mod metrics;
+pub mod middleware;
+#[cfg(feature = "oauth")]
+pub mod oauth;
+pub mod pool;
+pub mod retry;
 pub mod schema;
+#[cfg(feature = "storefront")]
+pub mod storefront;
+mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod throttle;
+pub mod transport;
 pub mod utils;
 #[cfg(feature = "webhooks")]
 pub mod webhooks;
 
-pub use auth::{ShopifyAuth, TokenData, TokenStore};
+pub use auth::{
+    offline_session_id, online_session_id, AssociatedUser, FileTokenStore, MemoryTokenStore,
+    ShopifyAuth, TokenData, TokenStore,
+};
+pub use builder::ShopifyBuilder;
+#[cfg(feature = "customer-account")]
+pub use customer_account::{
+    AuthorizationRequest, CustomerAccountConfig, CustomerAccountEndpoints, ShopifyCustomerAccount,
+};
 pub use graphql::{
     BulkConcurrencyOptions, BulkOperationPayload, BulkOperationsFilter, BulkWaitOptions,
-    GraphqlError, GraphqlResponse, ShopifyBulkOperation, ShopifyBulkStatus,
+    GraphqlError, GraphqlResponse, GraphqlWithMeta, PaginateOptions, QueryCost,
+    ShopifyBulkOperation, ShopifyBulkStatus, ThrottleStatus,
 };
+pub use metrics::{MetricLabels, ShopifyMetrics};
+pub use middleware::{GraphqlRequest, MiddlewareFuture, ShopifyMiddleware};
+pub use pool::{PoolOptions, PooledShopify, ShopifyPool};
+pub use retry::{RetryOn, RetryPolicy};
 pub use schema::{download_public_admin_schema, SHOPIFY_DEV_ADMIN_SCHEMA_PROXY};
+#[cfg(feature = "storefront")]
+pub use storefront::{ShopifyStorefront, StorefrontAuth, StorefrontConfig};
+pub use throttle::ThrottleConfig;
+pub use transport::{HttpTransport, ReqwestTransport};
 
 pub const DEFAULT_API_VERSION: &str = "2026-04";
 pub const MIN_API_VERSION: &str = "2026-04";
@@ -69,8 +104,20 @@ pub struct ShopifyConfig {
     #[cfg(feature = "webhooks")]
     pub shared_secret: Option<String>,
     pub token_store: Option<Arc<dyn TokenStore>>,
+    /// Key of the token in `token_store`. Defaults to the shop domain; set it
+    /// to an [`online_session_id`] for a per-user client.
+    pub session_id: Option<String>,
+    /// Replaces the token passed to [`Shopify::new`] with the one saved in
+    /// `token_store` on the first request.
+    pub load_token_from_store: bool,
     pub token_refresh_leeway: chrono::Duration,
     pub user_agent: String,
+    pub throttle: Option<ThrottleConfig>,
+    pub retry: RetryPolicy,
+    /// Receives request, throttling, cost, bulk and token refresh metrics.
+    pub metrics: Option<Arc<dyn ShopifyMetrics>>,
+    /// Layers run around every GraphQL and HTTP request, in order.
+    pub middleware: Vec<Arc<dyn ShopifyMiddleware>>,
 }
 
 impl Default for ShopifyConfig {
@@ -80,8 +127,14 @@ impl Default for ShopifyConfig {
             #[cfg(feature = "webhooks")]
             shared_secret: None,
             token_store: None,
+            session_id: None,
+            load_token_from_store: false,
             token_refresh_leeway: chrono::Duration::minutes(5),
             user_agent: VERSION.to_string(),
+            throttle: None,
+            retry: RetryPolicy::default(),
+            metrics: None,
+            middleware: Vec::new(),
         }
     }
 }
@@ -92,13 +145,24 @@ pub struct Shopify {
     #[cfg(feature = "webhooks")]
     pub(crate) shared_secret: Option<String>,
     auth: Arc<Mutex<ShopifyAuth>>,
-    client: reqwest::Client,
+    transport: Arc<dyn HttpTransport>,
+    default_headers: Arc<reqwest::header::HeaderMap>,
+    timeout: Option<std::time::Duration>,
     query_url: String,
     token_url: String,
     shop: String,
     shop_domain: String,
     token_store: Option<Arc<dyn TokenStore>>,
+    session_id: String,
+    load_token_from_store: bool,
+    token_loaded: Arc<AtomicBool>,
+    refresh_lock: Arc<tokio::sync::Mutex<()>>,
     token_refresh_leeway: chrono::Duration,
+    throttle: Option<Arc<throttle::CostLimiter>>,
+    retry: RetryPolicy,
+    idempotent: bool,
+    metrics: Option<Arc<dyn ShopifyMetrics>>,
+    middleware: Arc<[Arc<dyn ShopifyMiddleware>]>,
 }
 
 impl std::fmt::Debug for Shopify {
@@ -109,6 +173,7 @@ impl std::fmt::Debug for Shopify {
             .field("token_url", &self.token_url)
             .field("shop", &self.shop)
             .field("shop_domain", &self.shop_domain)
+            .field("session_id", &self.session_id)
             .finish_non_exhaustive()
     }
 }
@@ -118,6 +183,18 @@ pub enum ShopifyAPIError {
     #[error("connection failed")]
     ConnectionFailed(#[from] reqwest::Error),
 
+    /// A non-2xx response. Quote `request_id` when contacting Shopify support.
+    #[error(
+        "HTTP {status} (request id: {}): {body}",
+        request_id.as_deref().unwrap_or("unknown")
+    )]
+    Http {
+        status: reqwest::StatusCode,
+        body: String,
+        request_id: Option<String>,
+        retry_after: Option<std::time::Duration>,
+    },
+
     #[error("response body is broken")]
     ResponseBroken,
 
@@ -133,12 +210,31 @@ pub enum ShopifyAPIError {
     #[error("JSON parsing error: {0}")]
     JsonParseError(#[from] serde_json::Error),
 
+    #[error("JSONL parsing error on line {line}: {source}")]
+    JsonlParseError {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
     #[error("invalid API version `{version}`: minimum supported version is {minimum}")]
     InvalidApiVersion { version: String, minimum: String },
 
     #[error("authentication error: {0}")]
     Authentication(String),
 
+    #[error("access token is invalid or has been revoked")]
+    InvalidAccessToken,
+
+    #[error("invalid OAuth callback: {0}")]
+    InvalidOAuthCallback(String),
+
+    #[error("invalid session token: {0}")]
+    InvalidSessionToken(String),
+
     #[error("GraphQL returned errors: {0:?}")]
     GraphqlErrors(Vec<GraphqlError>),
 
@@ -155,41 +251,59 @@ pub enum ShopifyAPIError {
     Other(String),
 }
 
+impl ShopifyAPIError {
+    /// Whether the request may succeed if sent again: `429`, `5xx`,
+    /// throttling, and network failures such as timeouts or refused
+    /// connections.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ShopifyAPIError::Http { status, .. } => {
+                *status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            ShopifyAPIError::ConnectionFailed(err) => match err.status() {
+                Some(status) => {
+                    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                }
+                None => err.is_timeout() || err.is_connect() || err.is_request() || err.is_body(),
+            },
+            ShopifyAPIError::Throttled => true,
+            _ => false,
+        }
+    }
+
+    /// The HTTP status of a failed response, if any.
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        match self {
+            ShopifyAPIError::Http { status, .. } => Some(*status),
+            ShopifyAPIError::ConnectionFailed(err) => err.status(),
+            _ => None,
+        }
+    }
+
+    /// The `X-Request-Id` Shopify assigned to a failed response.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            ShopifyAPIError::Http { request_id, .. } => request_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// How long Shopify asked to wait before retrying, from `Retry-After`.
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            ShopifyAPIError::Http { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
 impl Shopify {
     pub fn new(
         shop: impl AsRef<str>,
         auth: ShopifyAuth,
         config: ShopifyConfig,
     ) -> Result<Self, ShopifyAPIError> {
-        let shop = shop.as_ref().to_string();
-        let shop_domain = normalize_shop_domain(&shop);
-        let query_url = format!(
-            "https://{}/admin/api/{}/graphql.json",
-            shop_domain, config.api_version
-        );
-        let token_url = format!("https://{}/admin/oauth/access_token", shop_domain);
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::USER_AGENT,
-            reqwest::header::HeaderValue::from_str(&config.user_agent)?,
-        );
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()?;
-
-        Ok(Self {
-            api_version: config.api_version,
-            #[cfg(feature = "webhooks")]
-            shared_secret: config.shared_secret,
-            auth: Arc::new(Mutex::new(auth)),
-            client,
-            query_url,
-            token_url,
-            shop,
-            shop_domain,
-            token_store: config.token_store,
-            token_refresh_leeway: config.token_refresh_leeway,
-        })
+        Self::builder(shop, auth).config(config).build()
     }
 
     pub fn get_shop(&self) -> &str {
@@ -200,6 +314,11 @@ impl Shopify {
         &self.shop_domain
     }
 
+    /// Key of this client's token in the configured [`TokenStore`].
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
     pub fn get_query_url(&self) -> &str {
         &self.query_url
     }
@@ -208,8 +327,53 @@ impl Shopify {
         &self.token_url
     }
 
-    pub(crate) fn client(&self) -> &reqwest::Client {
-        &self.client
+    /// Sends a request through the transport with the client's default
+    /// headers and timeout.
+    pub(crate) async fn send(
+        &self,
+        mut request: HttpRequest,
+    ) -> Result<HttpResponse, ShopifyAPIError> {
+        for (name, value) in self.default_headers.iter() {
+            if !request.headers.contains_key(name) {
+                request.headers.insert(name, value.clone());
+            }
+        }
+        request.timeout = request.timeout.or(self.timeout);
+        if self.middleware.is_empty() {
+            self.transport.send(request).await
+        } else {
+            self.send_through_middleware(request).await
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> Option<&dyn ShopifyMetrics> {
+        self.metrics.as_deref()
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// A clone of this client whose mutations are retried like queries.
+    ///
+    /// Only use it for mutations that are safe to apply twice, for example
+    /// those sent with Shopify's `@idempotent(key:)` directive, which are
+    /// also detected without it.
+    pub fn idempotent(&self) -> Self {
+        Self {
+            idempotent: true,
+            ..self.clone()
+        }
+    }
+
+    /// The retry policy for a GraphQL document: mutations that are not
+    /// idempotent are only retried when Shopify did not process them.
+    pub(crate) fn retry_policy_for(&self, query: &str) -> RetryPolicy {
+        if self.idempotent {
+            self.retry
+        } else {
+            self.retry.for_graphql(query)
+        }
     }
 
     pub fn replace_auth(&self, auth: ShopifyAuth) -> Result<(), ShopifyAPIError> {
@@ -222,7 +386,7 @@ impl Shopify {
     }
 }
 
-fn normalize_shop_domain(shop: &str) -> String {
+pub(crate) fn normalize_shop_domain(shop: &str) -> String {
     let shop = shop.trim().trim_start_matches("https://");
     let shop = shop.trim_start_matches("http://").trim_end_matches('/');
     if shop.ends_with(".myshopify.com") {
@@ -262,6 +426,41 @@ mod tests {
         assert_eq!(ApiVersion::new("2026-07").unwrap().as_str(), "2026-07");
     }
 
+    #[test]
+    fn http_errors_expose_retryability_and_request_id() {
+        let error = |status: u16| ShopifyAPIError::Http {
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            body: "{}".to_string(),
+            request_id: Some("abc-123".to_string()),
+            retry_after: utils::parse_retry_after("2"),
+        };
+
+        assert!(error(429).is_retryable());
+        assert!(error(503).is_retryable());
+        assert!(!error(404).is_retryable());
+        assert_eq!(error(500).request_id(), Some("abc-123"));
+        assert_eq!(
+            error(429).retry_after(),
+            Some(std::time::Duration::from_secs(2))
+        );
+        assert!(error(502).to_string().contains("request id: abc-123"));
+        assert_eq!(
+            utils::parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(std::time::Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn invalid_or_oversized_retry_after_is_ignored() {
+        assert_eq!(utils::parse_retry_after("99999999999999999999999"), None);
+        assert_eq!(utils::parse_retry_after("-1"), None);
+        assert_eq!(utils::parse_retry_after("NaN"), None);
+        assert_eq!(
+            utils::parse_retry_after("0.5"),
+            Some(std::time::Duration::from_millis(500))
+        );
+    }
+
     #[test]
     fn endpoints_are_built_from_normalized_shop_and_version() {
         let shopify = Shopify::new(
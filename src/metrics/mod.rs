@@ -0,0 +1,69 @@
+//! Hooks for counting requests, errors, throttling, query cost, bulk
+//! operations and token refreshes.
+//!
+//! Set a [`ShopifyMetrics`] on [`ShopifyConfig::metrics`](crate::ShopifyConfig::metrics)
+//! to receive them. With the `prometheus` feature, [`PrometheusMetrics`]
+//! exports them to a Prometheus registry.
+
+#[cfg(feature = "prometheus")]
+mod prometheus;
+
+#[cfg(feature = "prometheus")]
+pub use self::prometheus::PrometheusMetrics;
+
+use std::time::Duration;
+
+use crate::{QueryCost, ShopifyAPIError, ShopifyBulkStatus};
+
+/// Labels attached to every metric.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MetricLabels<'a> {
+    /// The shop domain, e.g. `my-shop.myshopify.com`.
+    pub shop: &'a str,
+    /// The GraphQL operation name, `anonymous` for unnamed operations,
+    /// `bulk_operation` for bulk polling and the grant type for token
+    /// refreshes.
+    pub operation: &'a str,
+}
+
+/// Observes the API usage of a [`Shopify`](crate::Shopify) client.
+///
+/// Every method defaults to a no-op. Implementations are called inline, so
+/// they should only update counters.
+pub trait ShopifyMetrics: Send + Sync {
+    /// A GraphQL request finished, after retries and throttling. `error` is
+    /// set when it failed, including responses with GraphQL `errors`.
+    fn request(
+        &self,
+        _labels: &MetricLabels<'_>,
+        _duration: Duration,
+        _error: Option<&ShopifyAPIError>,
+    ) {
+    }
+
+    /// A request waited for the throttle bucket, before sending or after a
+    /// `THROTTLED` response.
+    fn throttle_wait(&self, _labels: &MetricLabels<'_>, _wait: Duration) {}
+
+    /// Shopify reported the cost of a request.
+    fn query_cost(&self, _labels: &MetricLabels<'_>, _cost: &QueryCost) {}
+
+    /// [`Shopify::wait_for_bulk`](crate::Shopify::wait_for_bulk) saw a bulk
+    /// operation reach a final status after `duration`.
+    fn bulk_operation(
+        &self,
+        _labels: &MetricLabels<'_>,
+        _status: &ShopifyBulkStatus,
+        _duration: Duration,
+    ) {
+    }
+
+    /// An access token was requested or refreshed.
+    fn token_refresh(
+        &self,
+        _labels: &MetricLabels<'_>,
+        _duration: Duration,
+        _error: Option<&ShopifyAPIError>,
+    ) {
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use prometheus::{CounterVec, HistogramOpts, HistogramVec, Opts, Registry};
+
+use super::{MetricLabels, ShopifyMetrics};
+use crate::{QueryCost, ShopifyAPIError, ShopifyBulkStatus};
+
+/// Exports [`ShopifyMetrics`] to a Prometheus [`Registry`]:
+///
+/// - `shopify_requests_total{shop, operation, outcome}`
+/// - `shopify_request_duration_seconds{shop, operation}`
+/// - `shopify_throttle_wait_seconds_total{shop, operation}`
+/// - `shopify_query_cost_total{shop, operation}`
+/// - `shopify_bulk_operation_duration_seconds{shop, operation, status}`
+/// - `shopify_token_refreshes_total{shop, operation, outcome}`
+///
+/// `outcome` is `ok` or `error`.
+#[derive(Debug, Clone)]
+pub struct PrometheusMetrics {
+    requests: CounterVec,
+    request_duration: HistogramVec,
+    throttle_wait: CounterVec,
+    query_cost: CounterVec,
+    bulk_duration: HistogramVec,
+    token_refreshes: CounterVec,
+}
+
+impl PrometheusMetrics {
+    /// Creates the metrics and registers them with `registry`.
+    pub fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let metrics = Self {
+            requests: CounterVec::new(
+                Opts::new("shopify_requests_total", "Shopify GraphQL requests"),
+                &["shop", "operation", "outcome"],
+            )?,
+            request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "shopify_request_duration_seconds",
+                    "Shopify GraphQL request latency, including retries",
+                ),
+                &["shop", "operation"],
+            )?,
+            throttle_wait: CounterVec::new(
+                Opts::new(
+                    "shopify_throttle_wait_seconds_total",
+                    "Time spent waiting for the Shopify cost bucket",
+                ),
+                &["shop", "operation"],
+            )?,
+            query_cost: CounterVec::new(
+                Opts::new(
+                    "shopify_query_cost_total",
+                    "Shopify GraphQL query cost consumed",
+                ),
+                &["shop", "operation"],
+            )?,
+            bulk_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "shopify_bulk_operation_duration_seconds",
+                    "Time waited for Shopify bulk operations to finish",
+                )
+                .buckets(vec![1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 3600.0, 14400.0]),
+                &["shop", "operation", "status"],
+            )?,
+            token_refreshes: CounterVec::new(
+                Opts::new(
+                    "shopify_token_refreshes_total",
+                    "Shopify access token requests and refreshes",
+                ),
+                &["shop", "operation", "outcome"],
+            )?,
+        };
+
+        registry.register(Box::new(metrics.requests.clone()))?;
+        registry.register(Box::new(metrics.request_duration.clone()))?;
+        registry.register(Box::new(metrics.throttle_wait.clone()))?;
+        registry.register(Box::new(metrics.query_cost.clone()))?;
+        registry.register(Box::new(metrics.bulk_duration.clone()))?;
+        registry.register(Box::new(metrics.token_refreshes.clone()))?;
+
+        Ok(metrics)
+    }
+}
+
+fn outcome(error: Option<&ShopifyAPIError>) -> &'static str {
+    match error {
+        Some(_) => "error",
+        None => "ok",
+    }
+}
+
+impl ShopifyMetrics for PrometheusMetrics {
+    fn request(
+        &self,
+        labels: &MetricLabels<'_>,
+        duration: Duration,
+        error: Option<&ShopifyAPIError>,
+    ) {
+        self.requests
+            .with_label_values(&[labels.shop, labels.operation, outcome(error)])
+            .inc();
+        self.request_duration
+            .with_label_values(&[labels.shop, labels.operation])
+            .observe(duration.as_secs_f64());
+    }
+
+    fn throttle_wait(&self, labels: &MetricLabels<'_>, wait: Duration) {
+        self.throttle_wait
+            .with_label_values(&[labels.shop, labels.operation])
+            .inc_by(wait.as_secs_f64());
+    }
+
+    fn query_cost(&self, labels: &MetricLabels<'_>, cost: &QueryCost) {
+        self.query_cost
+            .with_label_values(&[labels.shop, labels.operation])
+            .inc_by(cost.actual_query_cost.unwrap_or(cost.requested_query_cost));
+    }
+
+    fn bulk_operation(
+        &self,
+        labels: &MetricLabels<'_>,
+        status: &ShopifyBulkStatus,
+        duration: Duration,
+    ) {
+        let status = format!("{status:?}").to_lowercase();
+        self.bulk_duration
+            .with_label_values(&[labels.shop, labels.operation, &status])
+            .observe(duration.as_secs_f64());
+    }
+
+    fn token_refresh(
+        &self,
+        labels: &MetricLabels<'_>,
+        _duration: Duration,
+        error: Option<&ShopifyAPIError>,
+    ) {
+        self.token_refreshes
+            .with_label_values(&[labels.shop, labels.operation, outcome(error)])
+            .inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_labelled_metrics() {
+        let registry = Registry::new();
+        let metrics = PrometheusMetrics::new(&registry).unwrap();
+        let labels = MetricLabels {
+            shop: "my-shop.myshopify.com",
+            operation: "ShopName",
+        };
+
+        metrics.request(&labels, Duration::from_millis(20), None);
+        metrics.request(
+            &labels,
+            Duration::from_millis(20),
+            Some(&ShopifyAPIError::Throttled),
+        );
+        metrics.throttle_wait(&labels, Duration::from_millis(500));
+
+        let families = registry.gather();
+        let requests = families
+            .iter()
+            .find(|family| family.name() == "shopify_requests_total")
+            .unwrap();
+        assert_eq!(requests.get_metric().len(), 2);
+        assert!(requests.get_metric().iter().all(|metric| metric
+            .get_label()
+            .iter()
+            .any(|label| label.name() == "operation" && label.value() == "ShopName")));
+
+        let wait = families
+            .iter()
+            .find(|family| family.name() == "shopify_throttle_wait_seconds_total")
+            .unwrap();
+        assert_eq!(wait.get_metric()[0].get_counter().get_value(), 0.5);
+    }
+}
@@ -0,0 +1,161 @@
+//! Layers that see, modify or answer the requests of a [`Shopify`] client.
+//!
+//! Middleware is set with [`ShopifyConfig::middleware`](crate::ShopifyConfig::middleware)
+//! and runs in order on the way out and in reverse order on the way back.
+//!
+//! ```rust,ignore
+//! struct ReadOnly;
+//!
+//! impl ShopifyMiddleware for ReadOnly {
+//!     fn before_graphql<'a>(
+//!         &'a self,
+//!         request: &'a mut GraphqlRequest,
+//!     ) -> MiddlewareFuture<'a, Option<GraphqlResponse<Value>>> {
+//!         Box::pin(async move {
+//!             if request.is_mutation() {
+//!                 return Err(ShopifyAPIError::Other("mutations are disabled".to_string()));
+//!             }
+//!             Ok(None)
+//!         })
+//!     }
+//! }
+//! ```
+
+use std::{future::Future, pin::Pin};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    transport::{HttpRequest, HttpResponse},
+    GraphqlResponse, Shopify, ShopifyAPIError,
+};
+
+pub type MiddlewareFuture<'a, T = ()> =
+    Pin<Box<dyn Future<Output = Result<T, ShopifyAPIError>> + Send + 'a>>;
+
+/// An outgoing GraphQL request.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphqlRequest {
+    pub query: String,
+    pub variables: Value,
+}
+
+impl GraphqlRequest {
+    pub fn new<Variables>(query: &str, variables: &Variables) -> Result<Self, ShopifyAPIError>
+    where
+        Variables: Serialize,
+    {
+        Ok(Self {
+            query: query.to_string(),
+            variables: serde_json::to_value(variables)?,
+        })
+    }
+
+    /// The name of the operation, when the query names it.
+    pub fn operation_name(&self) -> Option<String> {
+        crate::telemetry::operation_name(&self.query)
+    }
+
+    pub fn is_mutation(&self) -> bool {
+        crate::retry::is_mutation(&self.query)
+    }
+}
+
+/// A layer around the requests of a [`Shopify`] client.
+///
+/// The `before_*` hooks may modify the request, fail it, or answer it by
+/// returning a response, in which case later layers and the network are
+/// skipped. The `after_*` hooks of the layers that ran then see the response
+/// and may modify it. Every hook defaults to passing the request through.
+pub trait ShopifyMiddleware: Send + Sync {
+    /// Called for each [`Shopify::graphql_raw`] call and the helpers built on
+    /// it, before throttling and retries.
+    fn before_graphql<'a>(
+        &'a self,
+        _request: &'a mut GraphqlRequest,
+    ) -> MiddlewareFuture<'a, Option<GraphqlResponse<Value>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn after_graphql<'a>(
+        &'a self,
+        _request: &'a GraphqlRequest,
+        _response: &'a mut GraphqlResponse<Value>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Called for every HTTP request: each GraphQL attempt, token requests,
+    /// staged uploads and bulk result downloads through
+    /// [`Shopify::collect_bulk_jsonl`], [`Shopify::stream_bulk_jsonl`] and
+    /// [`Shopify::download_bulk_jsonl_to_file`]. The request carries the
+    /// access token header, which must not be logged.
+    fn before_request<'a>(
+        &'a self,
+        _request: &'a mut HttpRequest,
+    ) -> MiddlewareFuture<'a, Option<HttpResponse>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn after_response<'a>(
+        &'a self,
+        _request: &'a HttpRequest,
+        _response: &'a mut HttpResponse,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl Shopify {
+    pub(crate) async fn graphql_through_middleware(
+        &self,
+        mut request: GraphqlRequest,
+    ) -> Result<GraphqlResponse<Value>, ShopifyAPIError> {
+        let mut ran = 0;
+        let mut answered = None;
+        for layer in self.middleware.iter() {
+            ran += 1;
+            answered = layer.before_graphql(&mut request).await?;
+            if answered.is_some() {
+                break;
+            }
+        }
+
+        let mut response = match answered {
+            Some(response) => response,
+            None => {
+                self.observed_graphql(&request.query, &request.variables)
+                    .await?
+            }
+        };
+        for layer in self.middleware[..ran].iter().rev() {
+            layer.after_graphql(&request, &mut response).await?;
+        }
+        Ok(response)
+    }
+
+    pub(crate) async fn send_through_middleware(
+        &self,
+        mut request: HttpRequest,
+    ) -> Result<HttpResponse, ShopifyAPIError> {
+        let mut ran = 0;
+        let mut answered = None;
+        for layer in self.middleware.iter() {
+            ran += 1;
+            answered = layer.before_request(&mut request).await?;
+            if answered.is_some() {
+                break;
+            }
+        }
+
+        let mut response = match answered {
+            Some(response) => response,
+            None => self.transport.send(request.clone()).await?,
+        };
+        for layer in self.middleware[..ran].iter().rev() {
+            layer.after_response(&request, &mut response).await?;
+        }
+        Ok(response)
+    }
+}
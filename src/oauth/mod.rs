@@ -0,0 +1,418 @@
+mod session_token;
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+
+use reqwest::header::{HeaderValue, USER_AGENT};
+
+use crate::{
+    auth::request_token,
+    normalize_shop_domain,
+    transport::{HttpRequest, HttpTransport, ReqwestTransport, TransportFuture},
+    RetryPolicy, ShopifyAPIError, TokenData, VERSION,
+};
+pub use session_token::SessionTokenClaims;
+
+/// Helpers for installing a public app on a merchant shop through Shopify's
+/// authorization code grant.
+///
+/// 1. Redirect the merchant to [`ShopifyOAuth::authorization_url`], keeping the
+///    `state` in the merchant's session.
+/// 2. Check the redirect back to your app with
+///    [`ShopifyOAuth::validate_callback`].
+/// 3. Trade the code for an access token with [`ShopifyOAuth::exchange_code`].
+#[derive(Clone)]
+pub struct ShopifyOAuth {
+    pub client_id: String,
+    client_secret: String,
+    pub scopes: Vec<String>,
+    pub redirect_uri: String,
+    /// How old the callback `timestamp` may be.
+    pub max_callback_age: chrono::Duration,
+    /// Clock skew tolerated when checking session token `exp` and `nbf`.
+    pub session_token_leeway: chrono::Duration,
+    pub retry: RetryPolicy,
+    base_url: Option<String>,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl std::fmt::Debug for ShopifyOAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShopifyOAuth")
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .field("scopes", &self.scopes)
+            .field("redirect_uri", &self.redirect_uri)
+            .field("max_callback_age", &self.max_callback_age)
+            .field("session_token_leeway", &self.session_token_leeway)
+            .field("retry", &self.retry)
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Which kind of token the install flow grants.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum AccessMode {
+    #[default]
+    Offline,
+    /// Per-user token, requested with `grant_options[]=per-user`.
+    Online,
+}
+
+/// A callback whose HMAC, shop, timestamp and state have been checked.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AuthorizationCallback {
+    pub shop: String,
+    pub code: String,
+    pub host: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ShopifyOAuth {
+    pub fn new<I, S>(
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        scopes: I,
+        redirect_uri: impl Into<String>,
+    ) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            scopes: scopes.into_iter().map(Into::into).collect(),
+            redirect_uri: redirect_uri.into(),
+            max_callback_age: chrono::Duration::seconds(90),
+            session_token_leeway: chrono::Duration::seconds(5),
+            retry: RetryPolicy::default(),
+            base_url: None,
+            transport: Arc::new(ReqwestTransport::default()),
+        }
+    }
+
+    /// Sends token requests through `transport` instead of reqwest.
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Replaces `https://{shop}` in token request URLs, like
+    /// [`ShopifyBuilder::base_url`](crate::ShopifyBuilder::base_url) does for
+    /// the Admin client, for example to exchange codes with a mock server.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// The `/admin/oauth/access_token` URL of `shop`.
+    fn token_url(&self, shop: &str) -> String {
+        match &self.base_url {
+            Some(base_url) => format!("{base_url}/admin/oauth/access_token"),
+            None => format!("https://{shop}/admin/oauth/access_token"),
+        }
+    }
+
+    /// Sends a token request through the transport.
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        self.transport
+            .send(request.header(USER_AGENT, HeaderValue::from_static(VERSION)))
+    }
+
+    /// Generates a random `state` nonce to bind the callback to the merchant's
+    /// session.
+    pub fn generate_state() -> Result<String, ShopifyAPIError> {
+        let mut bytes = [0; 16];
+        getrandom::fill(&mut bytes)
+            .map_err(|err| ShopifyAPIError::Other(format!("random generation failed: {err}")))?;
+        Ok(crate::utils::hex_encode(&bytes))
+    }
+
+    /// Builds the `/admin/oauth/authorize` URL to redirect the merchant to.
+    pub fn authorization_url(
+        &self,
+        shop: &str,
+        state: &str,
+        access_mode: AccessMode,
+    ) -> Result<String, ShopifyAPIError> {
+        let shop = normalize_shop_domain(shop);
+        if !is_valid_shop_domain(&shop) {
+            return Err(ShopifyAPIError::InvalidOAuthCallback(format!(
+                "`{shop}` is not a myshopify.com domain"
+            )));
+        }
+
+        let mut url = reqwest::Url::parse(&format!("https://{shop}/admin/oauth/authorize"))
+            .map_err(|err| ShopifyAPIError::Other(err.to_string()))?;
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("client_id", &self.client_id)
+                .append_pair("scope", &self.scopes.join(","))
+                .append_pair("redirect_uri", &self.redirect_uri)
+                .append_pair("state", state);
+            if access_mode == AccessMode::Online {
+                query.append_pair("grant_options[]", "per-user");
+            }
+        }
+
+        Ok(url.into())
+    }
+
+    /// Validates the raw query string Shopify redirected to `redirect_uri`
+    /// with. See [`ShopifyOAuth::validate_callback`].
+    pub fn validate_callback_query(
+        &self,
+        query: &str,
+        expected_state: &str,
+    ) -> Result<AuthorizationCallback, ShopifyAPIError> {
+        let params = reqwest::Url::parse(&format!("https://callback.invalid/?{query}"))
+            .map_err(|err| ShopifyAPIError::InvalidOAuthCallback(err.to_string()))?
+            .query_pairs()
+            .into_owned()
+            .collect::<Vec<_>>();
+
+        self.validate_callback(params, expected_state)
+    }
+
+    /// Validates the parameters of the redirect back to `redirect_uri`.
+    ///
+    /// Checks the HMAC over the sorted parameters, that `shop` is a
+    /// myshopify.com hostname, that `timestamp` is recent and that `state`
+    /// matches the nonce issued with [`ShopifyOAuth::authorization_url`].
+    pub fn validate_callback<I, K, V>(
+        &self,
+        params: I,
+        expected_state: &str,
+    ) -> Result<AuthorizationCallback, ShopifyAPIError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut params = params
+            .into_iter()
+            .map(|(key, value)| (key.as_ref().to_string(), value.as_ref().to_string()))
+            .collect::<BTreeMap<_, _>>();
+        let invalid = |reason: &str| ShopifyAPIError::InvalidOAuthCallback(reason.to_string());
+
+        let hmac = params
+            .remove("hmac")
+            .ok_or_else(|| invalid("missing hmac"))?;
+        params.remove("signature");
+        if !verify_query_hmac(&self.client_secret, &params, &hmac) {
+            return Err(invalid("hmac does not match"));
+        }
+
+        let state = params
+            .get("state")
+            .ok_or_else(|| invalid("missing state"))?;
+        if !constant_time_eq(state.as_bytes(), expected_state.as_bytes()) {
+            return Err(invalid("state does not match"));
+        }
+
+        let shop = params.get("shop").ok_or_else(|| invalid("missing shop"))?;
+        if !is_valid_shop_domain(shop) {
+            return Err(invalid("shop is not a myshopify.com domain"));
+        }
+
+        let timestamp = params
+            .get("timestamp")
+            .and_then(|timestamp| timestamp.parse::<i64>().ok())
+            .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+            .ok_or_else(|| invalid("missing or invalid timestamp"))?;
+        if (Utc::now() - timestamp).abs() > self.max_callback_age {
+            return Err(invalid("timestamp is too old"));
+        }
+
+        let code = params.get("code").ok_or_else(|| invalid("missing code"))?;
+
+        Ok(AuthorizationCallback {
+            shop: shop.clone(),
+            code: code.clone(),
+            host: params.get("host").cloned(),
+            timestamp,
+        })
+    }
+
+    /// Exchanges the authorization code of a validated callback for a token.
+    pub async fn exchange_code(
+        &self,
+        callback: &AuthorizationCallback,
+    ) -> Result<TokenData, ShopifyAPIError> {
+        // Authorization codes are single-use.
+        request_token(
+            |request| self.send(request),
+            &self.retry.unprocessed_only(),
+            &self.token_url(&callback.shop),
+            &[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("code", callback.code.as_str()),
+            ],
+        )
+        .await
+    }
+}
+
+/// Whether `shop` looks like `my-shop.myshopify.com`.
+pub fn is_valid_shop_domain(shop: &str) -> bool {
+    let Some(name) = shop.strip_suffix(".myshopify.com") else {
+        return false;
+    };
+
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn verify_query_hmac(secret: &str, params: &BTreeMap<String, String>, hmac: &str) -> bool {
+    let Some(expected) = crate::utils::hex_decode(hmac) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+
+    mac.update(hmac_message(params).as_bytes());
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// The signed message: sorted `key=value` pairs joined with `&`, with `%` and
+/// `&` escaped in keys and values and `=` in keys, as Shopify does. Without
+/// it, a value containing `&k=v` signs like a separate parameter.
+fn hmac_message(params: &BTreeMap<String, String>) -> String {
+    fn escape(part: &str, is_key: bool) -> String {
+        let mut escaped = String::with_capacity(part.len());
+        for c in part.chars() {
+            match c {
+                '%' => escaped.push_str("%25"),
+                '&' => escaped.push_str("%26"),
+                '=' if is_key => escaped.push_str("%3D"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    params
+        .iter()
+        .map(|(key, value)| format!("{}={}", escape(key, true), escape(value, false)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .fold(0, |diff, (left, right)| diff | (left ^ right))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oauth() -> ShopifyOAuth {
+        ShopifyOAuth::new(
+            "client-id",
+            "hush",
+            ["read_products", "write_orders"],
+            "https://app.test/auth/callback",
+        )
+    }
+
+    fn signed_query(params: &[(&str, String)]) -> Vec<(String, String)> {
+        let sorted = params
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect::<BTreeMap<_, _>>();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"hush").unwrap();
+        mac.update(hmac_message(&sorted).as_bytes());
+        let hmac = crate::utils::hex_encode(&mac.finalize().into_bytes());
+
+        sorted
+            .into_iter()
+            .chain([("hmac".to_string(), hmac)])
+            .collect()
+    }
+
+    #[test]
+    fn authorization_url_includes_scopes_state_and_grant_options() {
+        let url = oauth()
+            .authorization_url("my-shop", "nonce", AccessMode::Online)
+            .unwrap();
+
+        assert!(url.starts_with("https://my-shop.myshopify.com/admin/oauth/authorize?"));
+        assert!(url.contains("scope=read_products%2Cwrite_orders"));
+        assert!(url.contains("state=nonce"));
+        assert!(url.contains("grant_options%5B%5D=per-user"));
+        assert!(oauth()
+            .authorization_url("evil.com/", "nonce", AccessMode::Offline)
+            .is_err());
+    }
+
+    #[test]
+    fn callback_validation_checks_hmac_state_shop_and_timestamp() {
+        let now = Utc::now().timestamp().to_string();
+        let params = signed_query(&[
+            ("code", "abc".to_string()),
+            ("shop", "my-shop.myshopify.com".to_string()),
+            ("state", "nonce".to_string()),
+            ("timestamp", now.clone()),
+        ]);
+
+        let callback = oauth().validate_callback(params.clone(), "nonce").unwrap();
+        assert_eq!(callback.shop, "my-shop.myshopify.com");
+        assert_eq!(callback.code, "abc");
+
+        assert!(oauth().validate_callback(params.clone(), "other").is_err());
+
+        let mut tampered = params;
+        tampered[0].1 = "xyz".to_string();
+        assert!(oauth().validate_callback(tampered, "nonce").is_err());
+
+        let stale = signed_query(&[
+            ("code", "abc".to_string()),
+            ("shop", "my-shop.myshopify.com".to_string()),
+            ("state", "nonce".to_string()),
+            ("timestamp", (Utc::now().timestamp() - 3600).to_string()),
+        ]);
+        assert!(oauth().validate_callback(stale, "nonce").is_err());
+
+        let foreign = signed_query(&[
+            ("code", "abc".to_string()),
+            ("shop", "evil.example.com".to_string()),
+            ("state", "nonce".to_string()),
+            ("timestamp", now),
+        ]);
+        assert!(oauth().validate_callback(foreign, "nonce").is_err());
+    }
+
+    #[test]
+    fn hmac_message_escapes_separators_like_shopify() {
+        let params = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        assert_eq!(
+            hmac_message(&params(&[("a=b", "50%"), ("code", "abc&state=nonce")])),
+            "a%3Db=50%25&code=abc%26state=nonce"
+        );
+        assert_ne!(
+            hmac_message(&params(&[("code", "abc&state=nonce")])),
+            hmac_message(&params(&[("code", "abc"), ("state", "nonce")]))
+        );
+    }
+}
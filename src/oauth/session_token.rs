@@ -0,0 +1,225 @@
+use base64::prelude::*;
+use chrono::Utc;
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::{is_valid_shop_domain, AccessMode, ShopifyOAuth};
+use crate::{auth::request_token, ShopifyAPIError, TokenData};
+
+const TOKEN_EXCHANGE_GRANT: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ID_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:id_token";
+const OFFLINE_TOKEN_TYPE: &str = "urn:shopify:params:oauth:token-type:offline-access-token";
+const ONLINE_TOKEN_TYPE: &str = "urn:shopify:params:oauth:token-type:online-access-token";
+
+/// Claims of an App Bridge session token.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SessionTokenClaims {
+    /// `https://{shop}/admin`
+    pub iss: String,
+    /// `https://{shop}`
+    pub dest: String,
+    /// The app's client id.
+    pub aud: String,
+    /// The staff member's user id.
+    pub sub: Option<String>,
+    pub exp: i64,
+    pub nbf: i64,
+    pub iat: i64,
+    pub jti: String,
+    pub sid: Option<String>,
+}
+
+impl SessionTokenClaims {
+    /// The shop domain the token was issued for, from `dest`.
+    pub fn shop(&self) -> &str {
+        self.dest
+            .strip_prefix("https://")
+            .unwrap_or(&self.dest)
+            .trim_end_matches('/')
+    }
+
+    pub fn user_id(&self) -> Option<u64> {
+        self.sub.as_deref()?.parse().ok()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+impl ShopifyOAuth {
+    /// Verifies an App Bridge session token, as sent in the `Authorization`
+    /// header with or without its `Bearer ` prefix.
+    ///
+    /// Checks the HS256 signature against the app secret, `exp` and `nbf`
+    /// (with [`ShopifyOAuth::session_token_leeway`]), that `aud` is this app,
+    /// and that `dest` and `iss` name the same myshopify.com shop.
+    pub fn verify_session_token(&self, token: &str) -> Result<SessionTokenClaims, ShopifyAPIError> {
+        let token = token.trim();
+        let token = token.strip_prefix("Bearer ").unwrap_or(token);
+        let invalid = |reason: &str| ShopifyAPIError::InvalidSessionToken(reason.to_string());
+
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("not a JWT"));
+        };
+
+        let jwt_header: JwtHeader =
+            decode_part(header).ok_or_else(|| invalid("malformed header"))?;
+        if jwt_header.alg != "HS256" {
+            return Err(invalid("unsupported signing algorithm"));
+        }
+
+        let signature = BASE64_URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| invalid("malformed signature"))?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.client_secret.as_bytes())
+            .map_err(|_| invalid("invalid app secret"))?;
+        mac.update(format!("{header}.{payload}").as_bytes());
+        if mac.verify_slice(&signature).is_err() {
+            return Err(invalid("signature does not match"));
+        }
+
+        let claims: SessionTokenClaims =
+            decode_part(payload).ok_or_else(|| invalid("malformed claims"))?;
+        let now = Utc::now().timestamp();
+        let leeway = self.session_token_leeway.num_seconds();
+        if claims.exp + leeway <= now {
+            return Err(invalid("token has expired"));
+        }
+        if claims.nbf - leeway > now {
+            return Err(invalid("token is not valid yet"));
+        }
+        if claims.aud != self.client_id {
+            return Err(invalid("token was issued for another app"));
+        }
+        if !is_valid_shop_domain(claims.shop())
+            || claims.iss.trim_end_matches('/') != format!("{}/admin", claims.dest)
+        {
+            return Err(invalid("issuer and destination do not match a shop"));
+        }
+
+        Ok(claims)
+    }
+
+    /// Verifies a session token and checks that it was issued for `shop`.
+    pub fn verify_session_token_for_shop(
+        &self,
+        token: &str,
+        shop: &str,
+    ) -> Result<SessionTokenClaims, ShopifyAPIError> {
+        let claims = self.verify_session_token(token)?;
+        if claims.shop() != crate::normalize_shop_domain(shop) {
+            return Err(ShopifyAPIError::InvalidSessionToken(
+                "token was issued for another shop".to_string(),
+            ));
+        }
+        Ok(claims)
+    }
+
+    /// Verifies a session token and exchanges it for an online or offline
+    /// access token for the shop it was issued for.
+    pub async fn exchange_session_token(
+        &self,
+        session_token: &str,
+        access_mode: AccessMode,
+    ) -> Result<TokenData, ShopifyAPIError> {
+        let claims = self.verify_session_token(session_token)?;
+        let session_token = session_token.trim();
+        let session_token = session_token
+            .strip_prefix("Bearer ")
+            .unwrap_or(session_token);
+        let requested_token_type = match access_mode {
+            AccessMode::Offline => OFFLINE_TOKEN_TYPE,
+            AccessMode::Online => ONLINE_TOKEN_TYPE,
+        };
+
+        request_token(
+            |request| self.send(request),
+            &self.retry,
+            &self.token_url(claims.shop()),
+            &[
+                ("grant_type", TOKEN_EXCHANGE_GRANT),
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("subject_token", session_token),
+                ("subject_token_type", ID_TOKEN_TYPE),
+                ("requested_token_type", requested_token_type),
+            ],
+        )
+        .await
+    }
+}
+
+fn decode_part<T>(part: &str) -> Option<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let bytes = BASE64_URL_SAFE_NO_PAD.decode(part).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn oauth() -> ShopifyOAuth {
+        ShopifyOAuth::new("client-id", "hush", ["read_products"], "https://app.test")
+    }
+
+    fn sign(claims: serde_json::Value, secret: &[u8]) -> String {
+        let header = BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+        let payload = BASE64_URL_SAFE_NO_PAD.encode(claims.to_string());
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(format!("{header}.{payload}").as_bytes());
+        let signature = BASE64_URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{header}.{payload}.{signature}")
+    }
+
+    fn claims(exp_offset: i64, aud: &str) -> serde_json::Value {
+        let now = Utc::now().timestamp();
+        json!({
+            "iss": "https://my-shop.myshopify.com/admin",
+            "dest": "https://my-shop.myshopify.com",
+            "aud": aud,
+            "sub": "42",
+            "exp": now + exp_offset,
+            "nbf": now - 60,
+            "iat": now - 60,
+            "jti": "id",
+            "sid": "session"
+        })
+    }
+
+    #[test]
+    fn verifies_signature_expiry_audience_and_shop() {
+        let token = sign(claims(60, "client-id"), b"hush");
+
+        let verified = oauth()
+            .verify_session_token(&format!("Bearer {token}"))
+            .unwrap();
+        assert_eq!(verified.shop(), "my-shop.myshopify.com");
+        assert_eq!(verified.user_id(), Some(42));
+        assert!(oauth()
+            .verify_session_token_for_shop(&token, "my-shop")
+            .is_ok());
+        assert!(oauth()
+            .verify_session_token_for_shop(&token, "other-shop")
+            .is_err());
+
+        let forged = sign(claims(60, "client-id"), b"wrong");
+        let expired = sign(claims(-60, "client-id"), b"hush");
+        let foreign = sign(claims(60, "other-app"), b"hush");
+        for token in [forged, expired, foreign] {
+            assert!(matches!(
+                oauth().verify_session_token(&token),
+                Err(ShopifyAPIError::InvalidSessionToken(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    normalize_shop_domain, offline_session_id,
+    transport::{HttpTransport, ReqwestTransport},
+    Shopify, ShopifyAPIError, ShopifyAuth, ShopifyConfig, TokenStore,
+};
+
+/// Limits of a [`ShopifyPool`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PoolOptions {
+    /// Clients unused for this long are dropped by [`ShopifyPool::evict_idle`],
+    /// which also runs on every lookup.
+    pub idle_timeout: Duration,
+    /// Requests in flight per shop through [`ShopifyPool::acquire`], so that
+    /// one busy shop cannot take every global slot.
+    pub max_concurrent_per_shop: usize,
+    /// Requests in flight across all shops through [`ShopifyPool::acquire`].
+    pub max_concurrent: usize,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(15 * 60),
+            max_concurrent_per_shop: 4,
+            max_concurrent: 64,
+        }
+    }
+}
+
+/// Per-shop [`Shopify`] clients sharing one transport and one
+/// [`ShopifyConfig`].
+///
+/// Clients are built on first use from the offline token saved in the
+/// config's [`TokenStore`], and keep their own auth and throttle state.
+///
+/// ```rust,ignore
+/// let pool = ShopifyPool::new(config, PoolOptions::default())?
+///     .with_app_credentials(client_id, client_secret);
+///
+/// let shopify = pool.acquire("my-shop").await?;
+/// let shop: ShopQuery = shopify.graphql(SHOP_QUERY, &json!({})).await?;
+///
+/// // In the `app/uninstalled` webhook handler:
+/// pool.handle_webhook(&topic, &shop_domain).await?;
+/// ```
+#[derive(Clone)]
+pub struct ShopifyPool {
+    config: ShopifyConfig,
+    options: PoolOptions,
+    token_store: Arc<dyn TokenStore>,
+    app_credentials: Option<(String, String)>,
+    transport: Arc<dyn HttpTransport>,
+    state: Arc<Mutex<PoolState>>,
+    permits: Arc<Semaphore>,
+}
+
+impl std::fmt::Debug for ShopifyPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShopifyPool")
+            .field("options", &self.options)
+            .field("clients", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Default)]
+struct PoolState {
+    clients: HashMap<String, PoolEntry>,
+    /// Per-shop concurrency limits. Kept apart from the clients so that a
+    /// shop invalidated with leases still out keeps its limit.
+    shop_permits: HashMap<String, Arc<Semaphore>>,
+    /// Client builds in flight per shop, dropped once none are left.
+    builds: HashMap<String, Builds>,
+}
+
+#[derive(Default)]
+struct Builds {
+    /// Bumped by every invalidation, so that a client built from a token
+    /// loaded before it is not inserted.
+    generation: u64,
+    in_flight: usize,
+}
+
+/// Counts a client build in [`PoolState::builds`] until it is dropped, which
+/// also covers lookups that are cancelled while the token loads.
+struct BuildGuard<'a> {
+    state: &'a Mutex<PoolState>,
+    shop_domain: &'a str,
+    generation: u64,
+}
+
+impl BuildGuard<'_> {
+    fn is_current(&self, state: &PoolState) -> bool {
+        state
+            .builds
+            .get(self.shop_domain)
+            .is_some_and(|builds| builds.generation == self.generation)
+    }
+}
+
+impl Drop for BuildGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(builds) = state.builds.get_mut(self.shop_domain) {
+            builds.in_flight -= 1;
+            if builds.in_flight == 0 {
+                state.builds.remove(self.shop_domain);
+            }
+        }
+    }
+}
+
+struct PoolEntry {
+    shopify: Shopify,
+    last_used: Instant,
+}
+
+/// A pooled client holding a concurrency slot for its shop until dropped.
+#[derive(Debug)]
+pub struct PooledShopify {
+    shopify: Shopify,
+    _shop_permit: OwnedSemaphorePermit,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl std::ops::Deref for PooledShopify {
+    type Target = Shopify;
+
+    fn deref(&self) -> &Shopify {
+        &self.shopify
+    }
+}
+
+impl ShopifyPool {
+    /// Creates a pool sending requests through a default [`ReqwestTransport`].
+    /// `config.token_store` is required.
+    pub fn new(config: ShopifyConfig, options: PoolOptions) -> Result<Self, ShopifyAPIError> {
+        let token_store = config.token_store.clone().ok_or_else(|| {
+            ShopifyAPIError::Other("ShopifyPool requires ShopifyConfig::token_store".to_string())
+        })?;
+
+        Ok(Self {
+            config,
+            options,
+            token_store,
+            app_credentials: None,
+            transport: Arc::new(ReqwestTransport::default()),
+            state: Arc::new(Mutex::new(PoolState::default())),
+            permits: Arc::new(Semaphore::new(options.max_concurrent.max(1))),
+        })
+    }
+
+    /// Lets stored expiring offline tokens be refreshed with the app's
+    /// credentials.
+    pub fn with_app_credentials(
+        mut self,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        self.app_credentials = Some((client_id.into(), client_secret.into()));
+        self
+    }
+
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// The client for `shop`, built from its stored token on first use.
+    pub async fn client(&self, shop: &str) -> Result<Shopify, ShopifyAPIError> {
+        Ok(self.entry(shop).await?.0)
+    }
+
+    /// Waits for a concurrency slot for `shop`, then for a global one, and
+    /// returns the shop's client holding both until it is dropped.
+    ///
+    /// Waiters are served in order, and the per-shop limit keeps a busy shop
+    /// from queueing ahead of every other shop.
+    pub async fn acquire(&self, shop: &str) -> Result<PooledShopify, ShopifyAPIError> {
+        let (shopify, shop_permits) = self.entry(shop).await?;
+        let closed = |_| ShopifyAPIError::Other("shop pool is closed".to_string());
+        let shop_permit = shop_permits.acquire_owned().await.map_err(closed)?;
+        let permit = self.permits.clone().acquire_owned().await.map_err(closed)?;
+
+        Ok(PooledShopify {
+            shopify,
+            _shop_permit: shop_permit,
+            _permit: permit,
+        })
+    }
+
+    async fn entry(&self, shop: &str) -> Result<(Shopify, Arc<Semaphore>), ShopifyAPIError> {
+        self.evict_idle();
+        let shop_domain = normalize_shop_domain(shop);
+        loop {
+            let build = {
+                let mut state = self.state();
+                if let Some(entry) = state.clients.get_mut(&shop_domain) {
+                    entry.last_used = Instant::now();
+                    let shopify = entry.shopify.clone();
+                    return Ok((shopify, self.shop_permits(&mut state, &shop_domain)));
+                }
+                let builds = state.builds.entry(shop_domain.clone()).or_default();
+                builds.in_flight += 1;
+                BuildGuard {
+                    state: &self.state,
+                    shop_domain: &shop_domain,
+                    generation: builds.generation,
+                }
+            };
+
+            let shopify = self.build_client(&shop_domain).await?;
+            let mut state = self.state();
+            // The shop was invalidated while its token was loading, for
+            // example by an uninstall: load it again. `state` is released
+            // before `build`, whose drop takes the lock.
+            if !build.is_current(&state) {
+                continue;
+            }
+            // Another caller may have built the client while this one loaded it.
+            let entry = state
+                .clients
+                .entry(shop_domain.clone())
+                .or_insert_with(|| PoolEntry {
+                    shopify,
+                    last_used: Instant::now(),
+                });
+            let shopify = entry.shopify.clone();
+            return Ok((shopify, self.shop_permits(&mut state, &shop_domain)));
+        }
+    }
+
+    fn shop_permits(&self, state: &mut PoolState, shop_domain: &str) -> Arc<Semaphore> {
+        state
+            .shop_permits
+            .entry(shop_domain.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_concurrent_per_shop())))
+            .clone()
+    }
+
+    fn max_concurrent_per_shop(&self) -> usize {
+        self.options.max_concurrent_per_shop.max(1)
+    }
+
+    async fn build_client(&self, shop_domain: &str) -> Result<Shopify, ShopifyAPIError> {
+        let token = self
+            .token_store
+            .load_token(&offline_session_id(shop_domain))
+            .await?
+            .ok_or_else(|| {
+                ShopifyAPIError::Authentication(format!(
+                    "no offline token stored for {shop_domain}; the app must be installed first"
+                ))
+            })?;
+        let auth = match &self.app_credentials {
+            Some((client_id, client_secret)) if token.refresh_token.is_some() => {
+                ShopifyAuth::expiring_offline_token(client_id, client_secret, token)
+            }
+            _ => ShopifyAuth::AccessToken(token.access_token),
+        };
+
+        Shopify::builder(shop_domain, auth)
+            .config(ShopifyConfig {
+                session_id: None,
+                load_token_from_store: false,
+                ..self.config.clone()
+            })
+            .transport(self.transport.clone())
+            .build()
+    }
+
+    /// Drops clients that have been idle for
+    /// [`PoolOptions::idle_timeout`] and have no request in flight. Returns how
+    /// many were dropped.
+    pub fn evict_idle(&self) -> usize {
+        let mut state = self.state();
+        let PoolState {
+            clients,
+            shop_permits,
+            ..
+        } = &mut *state;
+        let in_use = |shop: &str| shop_permits.get(shop).is_some_and(is_shared);
+
+        let before = clients.len();
+        clients.retain(|shop, entry| {
+            entry.last_used.elapsed() < self.options.idle_timeout || in_use(shop)
+        });
+        let evicted = before - clients.len();
+        shop_permits.retain(|shop, permits| clients.contains_key(shop) || is_shared(permits));
+        evicted
+    }
+
+    /// Drops the client of `shop`, so the next lookup reloads its token.
+    /// Requests still holding a slot for the shop keep counting against
+    /// [`PoolOptions::max_concurrent_per_shop`].
+    pub fn invalidate(&self, shop: &str) -> bool {
+        let shop_domain = normalize_shop_domain(shop);
+        let mut state = self.state();
+        if let Some(builds) = state.builds.get_mut(&shop_domain) {
+            builds.generation += 1;
+        }
+        if !state.shop_permits.get(&shop_domain).is_some_and(is_shared) {
+            state.shop_permits.remove(&shop_domain);
+        }
+        state.clients.remove(&shop_domain).is_some()
+    }
+
+    /// Drops the client of an uninstalled shop and deletes its stored token.
+    pub async fn remove_shop(&self, shop: &str) -> Result<(), ShopifyAPIError> {
+        let shop_domain = normalize_shop_domain(shop);
+        // Deleting first means a client built after the invalidation can
+        // only find the token gone.
+        let deleted = self
+            .token_store
+            .delete_token(&offline_session_id(&shop_domain))
+            .await;
+        self.invalidate(&shop_domain);
+        deleted
+    }
+
+    /// Calls [`ShopifyPool::remove_shop`] for `app/uninstalled` webhooks.
+    /// Returns whether the topic was handled.
+    pub async fn handle_webhook(&self, topic: &str, shop: &str) -> Result<bool, ShopifyAPIError> {
+        if !matches!(topic, "app/uninstalled" | "APP_UNINSTALLED") {
+            return Ok(false);
+        }
+        self.remove_shop(shop).await?;
+        Ok(true)
+    }
+
+    pub fn len(&self) -> usize {
+        self.state().clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Whether a shop's limit is held by anything besides the pool: a lease, or
+/// a caller of [`ShopifyPool::acquire`] that has not taken its slot yet.
+/// Clones are only made under the pool's lock, so the count is stable there.
+fn is_shared(permits: &Arc<Semaphore>) -> bool {
+    Arc::strong_count(permits) > 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryTokenStore, TokenData};
+
+    #[tokio::test]
+    async fn invalidation_keeps_limits_handed_out_and_forgets_finished_builds() {
+        let store = Arc::new(MemoryTokenStore::new());
+        store
+            .save_token("shop-a.myshopify.com", TokenData::never_expiring("token"))
+            .await
+            .unwrap();
+        let pool = ShopifyPool::new(
+            ShopifyConfig {
+                token_store: Some(store),
+                ..ShopifyConfig::default()
+            },
+            PoolOptions {
+                max_concurrent_per_shop: 1,
+                ..PoolOptions::default()
+            },
+        )
+        .unwrap();
+
+        // A caller that looked the shop up but has not taken its slot yet.
+        let (_, permits) = pool.entry("shop-a").await.unwrap();
+        assert!(pool.state().builds.is_empty());
+        assert!(pool.invalidate("shop-a"));
+        let _slot = permits.acquire_owned().await.unwrap();
+
+        let busy = tokio::time::timeout(Duration::from_millis(20), pool.acquire("shop-a")).await;
+        assert!(busy.is_err());
+        assert!(pool.state().builds.is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    future::Future,
+    hash::{BuildHasher, RandomState},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::ShopifyAPIError;
+
+/// Which failures a [`RetryPolicy`] retries.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RetryOn {
+    /// `429 Too Many Requests` and GraphQL throttling.
+    pub rate_limited: bool,
+    /// `5xx` responses.
+    pub server_errors: bool,
+    /// Connections that could not be established. The request never reached
+    /// Shopify, so these are safe to retry even for single-use requests.
+    pub connect_errors: bool,
+    /// Timeouts and connections dropped mid-request.
+    pub timeouts: bool,
+}
+
+impl Default for RetryOn {
+    fn default() -> Self {
+        Self {
+            rate_limited: true,
+            server_errors: true,
+            connect_errors: true,
+            timeouts: true,
+        }
+    }
+}
+
+/// How failed requests are retried.
+///
+/// Applies to GraphQL queries, token requests, staged uploads and bulk result
+/// downloads. Mutations are only retried when they are idempotent, see
+/// [`Shopify::idempotent`](crate::Shopify::idempotent).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first one. `0` and `1` disable retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound of every delay, including waits asked for by
+    /// `Retry-After`.
+    pub max_delay: Duration,
+    /// Factor applied to the delay after every attempt.
+    pub multiplier: f64,
+    /// Picks each delay at random between half and all of the backoff delay,
+    /// so that clients failing together do not retry together.
+    pub jitter: bool,
+    /// Waits for `Retry-After` instead of the backoff delay when Shopify sends
+    /// it, up to [`RetryPolicy::max_delay`].
+    pub respect_retry_after: bool,
+    pub retry_on: RetryOn,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: true,
+            respect_retry_after: true,
+            retry_on: RetryOn::default(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// The same policy, restricted to failures where Shopify did not process
+    /// the request. Used for single-use requests such as refresh token and
+    /// authorization code exchanges.
+    pub(crate) fn unprocessed_only(self) -> Self {
+        Self {
+            retry_on: RetryOn {
+                rate_limited: self.retry_on.rate_limited,
+                server_errors: false,
+                connect_errors: self.retry_on.connect_errors,
+                timeouts: false,
+            },
+            ..self
+        }
+    }
+
+    /// The policy for a GraphQL document: mutations are only retried when
+    /// Shopify did not process them, unless they carry `@idempotent(key:)`.
+    pub(crate) fn for_graphql(self, query: &str) -> Self {
+        if is_mutation(query) && !query.contains("@idempotent") {
+            self.unprocessed_only()
+        } else {
+            self
+        }
+    }
+
+    pub fn should_retry(&self, error: &ShopifyAPIError) -> bool {
+        let retry_on = &self.retry_on;
+        match error {
+            ShopifyAPIError::Throttled => retry_on.rate_limited,
+            ShopifyAPIError::Http { status, .. } => status_is_retried(retry_on, *status),
+            ShopifyAPIError::ConnectionFailed(err) => match err.status() {
+                Some(status) => status_is_retried(retry_on, status),
+                None if err.is_connect() => retry_on.connect_errors,
+                None => {
+                    retry_on.timeouts && (err.is_timeout() || err.is_request() || err.is_body())
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// The delay before retry number `retry` (starting at 1) after `error`.
+    pub fn delay(&self, retry: u32, error: &ShopifyAPIError) -> Duration {
+        if self.respect_retry_after {
+            if let Some(retry_after) = error.retry_after() {
+                return retry_after.min(self.max_delay);
+            }
+        }
+
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(retry.saturating_sub(1) as i32);
+        let delay = self
+            .initial_delay
+            .mul_f64(factor.min(u32::MAX as f64))
+            .min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(0.5 + random_unit() / 2.0)
+        } else {
+            delay
+        }
+    }
+
+    /// Runs `request` until it succeeds, fails with an error this policy does
+    /// not retry, or runs out of attempts.
+    pub(crate) async fn run<T, F, Fut>(&self, mut request: F) -> Result<T, ShopifyAPIError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ShopifyAPIError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(err) if attempt < self.max_attempts && self.should_retry(&err) => {
+                    let delay = self.delay(attempt, &err);
+                    crate::telemetry::Span::current().record_retries(attempt);
+                    log::debug!("shopify request failed ({err}), retrying in {delay:?}");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn status_is_retried(retry_on: &RetryOn, status: reqwest::StatusCode) -> bool {
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        retry_on.rate_limited
+    } else {
+        status.is_server_error() && retry_on.server_errors
+    }
+}
+
+/// A random number in `[0, 1)` from the standard library's hasher seeds.
+fn random_unit() -> f64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let hash = RandomState::new().hash_one(COUNTER.fetch_add(1, Ordering::Relaxed));
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Whether a GraphQL document defines a mutation operation.
+pub(crate) fn is_mutation(query: &str) -> bool {
+    let mut depth = 0usize;
+    let mut word = String::new();
+    for line in query.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        for c in line.chars().chain([' ']) {
+            if depth == 0 && (c.is_alphanumeric() || c == '_') {
+                word.push(c);
+                continue;
+            }
+            if word == "mutation" {
+                return true;
+            }
+            word.clear();
+            match c {
+                '{' | '(' => depth += 1,
+                '}' | ')' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn http_error(status: u16, retry_after: Option<Duration>) -> ShopifyAPIError {
+        ShopifyAPIError::Http {
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            body: String::new(),
+            request_id: None,
+            retry_after,
+        }
+    }
+
+    #[test]
+    fn backoff_and_retry_after_are_capped_at_max_delay() {
+        let policy = RetryPolicy {
+            jitter: false,
+            max_delay: Duration::from_secs(3),
+            ..RetryPolicy::default()
+        };
+        let error = http_error(503, None);
+
+        assert_eq!(policy.delay(1, &error), Duration::from_millis(500));
+        assert_eq!(policy.delay(3, &error), Duration::from_secs(2));
+        assert_eq!(policy.delay(10, &error), Duration::from_secs(3));
+        assert_eq!(
+            policy.delay(1, &http_error(429, Some(Duration::from_secs(2)))),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            policy.delay(1, &http_error(429, Some(Duration::from_secs(86400)))),
+            Duration::from_secs(3)
+        );
+
+        let jittered = RetryPolicy::default().delay(2, &error);
+        assert!(jittered >= Duration::from_millis(500) && jittered <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn retries_only_selected_error_classes() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry(&http_error(429, None)));
+        assert!(policy.should_retry(&http_error(502, None)));
+        assert!(!policy.should_retry(&http_error(400, None)));
+        assert!(!policy
+            .unprocessed_only()
+            .should_retry(&http_error(502, None)));
+        assert!(policy
+            .unprocessed_only()
+            .should_retry(&http_error(429, None)));
+    }
+
+    #[tokio::test]
+    async fn run_stops_after_max_attempts() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::ZERO,
+            ..RetryPolicy::default()
+        };
+        let mut attempts = 0;
+
+        let result: Result<(), _> = policy
+            .run(|| {
+                attempts += 1;
+                async { Err(http_error(500, None)) }
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn detects_mutations() {
+        assert!(is_mutation("mutation { shopUpdate { id } }"));
+        assert!(is_mutation("# comment\n  mutation Named($id: ID!) { x }"));
+        assert!(!is_mutation("query { shop { name } }"));
+        assert!(!is_mutation("{ shop { name } }"));
+        assert!(!is_mutation("query { mutation: shop { name } }"));
+        assert!(is_mutation(
+            "fragment F on Shop { id }\nmutation { x { ...F } }"
+        ));
+    }
+
+    #[test]
+    fn only_idempotent_mutations_keep_the_full_policy() {
+        let policy = RetryPolicy::default();
+        assert!(
+            policy
+                .for_graphql("query { shop { name } }")
+                .retry_on
+                .server_errors
+        );
+        assert!(
+            !policy
+                .for_graphql("mutation { x { id } }")
+                .retry_on
+                .server_errors
+        );
+        assert!(
+            policy
+                .for_graphql("mutation @idempotent(key: \"k\") { x { id } }")
+                .retry_on
+                .server_errors
+        );
+    }
+}
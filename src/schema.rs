@@ -1,6 +1,10 @@
 use serde_json::json;
 
-use crate::{ApiVersion, Shopify, ShopifyAPIError};
+use crate::{
+    transport::{HttpRequest, HttpTransport, ReqwestTransport},
+    utils::error_for_status,
+    ApiVersion, Shopify, ShopifyAPIError,
+};
 
 pub const ADMIN_SCHEMA_INTROSPECTION_QUERY: &str = include_str!("../schema_dl.graphql");
 pub const SHOPIFY_DEV_ADMIN_SCHEMA_PROXY: &str = "https://shopify.dev/admin-graphql-direct-proxy";
@@ -10,20 +14,13 @@ pub async fn download_public_admin_schema(
 ) -> Result<serde_json::Value, ShopifyAPIError> {
     let api_version = ApiVersion::new(api_version.as_ref())?;
     let url = format!("{SHOPIFY_DEV_ADMIN_SCHEMA_PROXY}/{api_version}");
-    let response = reqwest::Client::new()
-        .post(url)
-        .json(&json!({
-            "query": ADMIN_SCHEMA_INTROSPECTION_QUERY,
-            "variables": {},
-        }))
-        .send()
-        .await?
-        .error_for_status()?;
+    let request = HttpRequest::post(url).json(&json!({
+        "query": ADMIN_SCHEMA_INTROSPECTION_QUERY,
+        "variables": {},
+    }))?;
+    let response = ReqwestTransport::default().send(request).await?;
 
-    response
-        .json()
-        .await
-        .map_err(ShopifyAPIError::ConnectionFailed)
+    error_for_status(response).await?.json().await
 }
 
 impl Shopify {
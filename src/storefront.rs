@@ -0,0 +1,260 @@
+use std::sync::Arc;
+
+use reqwest::header::{HeaderName, HeaderValue, USER_AGENT};
+use serde_json::Value;
+
+use crate::{
+    normalize_shop_domain, throttle,
+    transport::{HttpRequest, HttpTransport, ReqwestTransport},
+    utils::{error_for_status, ReadJsonTreeSteps},
+    ApiVersion, GraphqlResponse, GraphqlWithMeta, RetryPolicy, ShopifyAPIError, ThrottleConfig,
+    VERSION,
+};
+
+const PUBLIC_TOKEN_HEADER: HeaderName =
+    HeaderName::from_static("x-shopify-storefront-access-token");
+const PRIVATE_TOKEN_HEADER: HeaderName =
+    HeaderName::from_static("shopify-storefront-private-token");
+const BUYER_IP_HEADER: HeaderName = HeaderName::from_static("shopify-storefront-buyer-ip");
+
+/// How a [`ShopifyStorefront`] authenticates.
+#[derive(Clone)]
+pub enum StorefrontAuth {
+    /// A public access token, safe to ship to browsers and apps.
+    Public(String),
+    /// A private access token for server-side requests. Send the buyer's IP
+    /// with [`ShopifyStorefront::with_buyer_ip`] so Shopify rate-limits per
+    /// buyer rather than per server.
+    Private(String),
+}
+
+impl std::fmt::Debug for StorefrontAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Public(_) => f.write_str("Public(..)"),
+            Self::Private(_) => f.write_str("Private(..)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StorefrontConfig {
+    pub api_version: ApiVersion,
+    pub user_agent: String,
+    pub throttle: Option<ThrottleConfig>,
+    pub retry: RetryPolicy,
+}
+
+impl Default for StorefrontConfig {
+    fn default() -> Self {
+        Self {
+            api_version: ApiVersion::default(),
+            user_agent: VERSION.to_string(),
+            throttle: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// A client for the Storefront API at `/api/{version}/graphql.json`.
+///
+/// ```rust,ignore
+/// let storefront = ShopifyStorefront::new(
+///     "my-shop",
+///     StorefrontAuth::Private(private_token),
+///     StorefrontConfig::default(),
+/// )?;
+/// let products: Value = storefront
+///     .with_buyer_ip(buyer_ip)
+///     .graphql("{ products(first: 3) { nodes { title } } }", &json!({}))
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct ShopifyStorefront {
+    pub api_version: ApiVersion,
+    auth: StorefrontAuth,
+    buyer_ip: Option<HeaderValue>,
+    transport: Arc<dyn HttpTransport>,
+    user_agent: HeaderValue,
+    query_url: String,
+    shop_domain: String,
+    throttle: Option<Arc<throttle::CostLimiter>>,
+    retry: RetryPolicy,
+}
+
+impl std::fmt::Debug for ShopifyStorefront {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShopifyStorefront")
+            .field("api_version", &self.api_version)
+            .field("auth", &self.auth)
+            .field("query_url", &self.query_url)
+            .field("shop_domain", &self.shop_domain)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ShopifyStorefront {
+    pub fn new(
+        shop: impl AsRef<str>,
+        auth: StorefrontAuth,
+        config: StorefrontConfig,
+    ) -> Result<Self, ShopifyAPIError> {
+        let shop_domain = normalize_shop_domain(shop.as_ref());
+
+        Ok(Self {
+            query_url: format!(
+                "https://{shop_domain}/api/{}/graphql.json",
+                config.api_version
+            ),
+            api_version: config.api_version,
+            auth,
+            buyer_ip: None,
+            transport: Arc::new(ReqwestTransport::default()),
+            user_agent: HeaderValue::from_str(&config.user_agent)?,
+            shop_domain,
+            throttle: config
+                .throttle
+                .map(|throttle| Arc::new(throttle::CostLimiter::new(throttle))),
+            retry: config.retry,
+        })
+    }
+
+    /// Sends requests through `transport`, for example the one of an Admin
+    /// [`Shopify`](crate::Shopify) client to share its connection pool.
+    pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Replaces `https://{shop}.myshopify.com` in the Storefront API URL.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.query_url = format!(
+            "{}/api/{}/graphql.json",
+            base_url.trim_end_matches('/'),
+            self.api_version
+        );
+        self
+    }
+
+    /// A clone of this client that sends the buyer's IP address with each
+    /// request.
+    pub fn with_buyer_ip(&self, buyer_ip: impl AsRef<str>) -> Result<Self, ShopifyAPIError> {
+        Ok(Self {
+            buyer_ip: Some(HeaderValue::from_str(buyer_ip.as_ref())?),
+            ..self.clone()
+        })
+    }
+
+    pub fn shop_domain(&self) -> &str {
+        &self.shop_domain
+    }
+
+    pub fn get_query_url(&self) -> &str {
+        &self.query_url
+    }
+
+    /// Sends a GraphQL request and returns the raw response. Retries and
+    /// `THROTTLED` replays follow [`StorefrontConfig::retry`] and
+    /// [`StorefrontConfig::throttle`].
+    pub async fn graphql_raw<Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<GraphqlResponse<Value>, ShopifyAPIError>
+    where
+        Variables: serde::Serialize,
+    {
+        let Some(limiter) = &self.throttle else {
+            return self.send_graphql(query, variables).await;
+        };
+
+        limiter
+            .run(query, |_| {}, || self.send_graphql(query, variables))
+            .await
+    }
+
+    async fn send_graphql<Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<GraphqlResponse<Value>, ShopifyAPIError>
+    where
+        Variables: serde::Serialize,
+    {
+        let request = self.authorize(
+            HttpRequest::post(&self.query_url)
+                .json(&serde_json::json!({ "query": query, "variables": variables }))?,
+        )?;
+        self.retry
+            .for_graphql(query)
+            .run(|| async {
+                let response = self.transport.send(request.clone()).await?;
+                let response: GraphqlResponse<Value> =
+                    error_for_status(response).await?.json().await?;
+                // Without a limiter, `THROTTLED` is retried like a 429.
+                if self.throttle.is_none() && response.is_throttled() {
+                    return Err(ShopifyAPIError::Throttled);
+                }
+                Ok(response)
+            })
+            .await
+    }
+
+    fn authorize(&self, request: HttpRequest) -> Result<HttpRequest, ShopifyAPIError> {
+        let (name, token) = match &self.auth {
+            StorefrontAuth::Public(token) => (PUBLIC_TOKEN_HEADER, token),
+            StorefrontAuth::Private(token) => (PRIVATE_TOKEN_HEADER, token),
+        };
+        let mut token = HeaderValue::from_str(token)?;
+        token.set_sensitive(true);
+
+        let mut request = request
+            .header(USER_AGENT, self.user_agent.clone())
+            .header(name, token);
+        if let Some(buyer_ip) = &self.buyer_ip {
+            request = request.header(BUYER_IP_HEADER, buyer_ip.clone());
+        }
+        Ok(request)
+    }
+
+    pub async fn graphql<ReturnType, Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<ReturnType, ShopifyAPIError>
+    where
+        ReturnType: serde::de::DeserializeOwned,
+        Variables: serde::Serialize,
+    {
+        Ok(self.graphql_with_meta(query, variables).await?.data)
+    }
+
+    pub async fn graphql_with_meta<ReturnType, Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+    ) -> Result<GraphqlWithMeta<ReturnType>, ShopifyAPIError>
+    where
+        ReturnType: serde::de::DeserializeOwned,
+        Variables: serde::Serialize,
+    {
+        self.graphql_raw(query, variables).await?.into_data()
+    }
+
+    pub async fn graphql_at_path<ReturnType, Variables>(
+        &self,
+        query: &str,
+        variables: &Variables,
+        json_finder: &[ReadJsonTreeSteps<'_>],
+    ) -> Result<ReturnType, ShopifyAPIError>
+    where
+        ReturnType: serde::de::DeserializeOwned,
+        Variables: serde::Serialize,
+    {
+        Ok(self
+            .graphql_with_meta::<Value, _>(query, variables)
+            .await?
+            .at_path(json_finder)?
+            .data)
+    }
+}
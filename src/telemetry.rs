@@ -0,0 +1,252 @@
+//! Spans emitted with the `tracing` feature. Without it, every helper here is
+//! a no-op.
+//!
+//! Spans only ever carry the shop domain, operation names, numbers, request
+//! ids and error kinds: tokens, secrets, variables, response bodies and the
+//! error messages that may quote them are never recorded.
+
+use std::{future::Future, time::Instant};
+
+use reqwest::StatusCode;
+
+use crate::{QueryCost, ShopifyAPIError, ShopifyBulkStatus};
+
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    inner: tracing::Span,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+impl Span {
+    /// `shopify.graphql`, covering throttling waits and retries of a query.
+    pub(crate) fn graphql(
+        shop: &str,
+        api_version: &str,
+        operation: Option<&str>,
+        query: &str,
+    ) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            inner: tracing::info_span!(
+                "shopify.graphql",
+                shop,
+                api_version,
+                operation,
+                operation_type = if crate::retry::is_mutation(query) {
+                    "mutation"
+                } else {
+                    "query"
+                },
+                cost = tracing::field::Empty,
+                throttle_available = tracing::field::Empty,
+                http.status = tracing::field::Empty,
+                retries = tracing::field::Empty,
+                throttled_retries = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+                http.request_id = tracing::field::Empty,
+                error = tracing::field::Empty,
+            ),
+        }
+    }
+
+    /// `shopify.bulk.wait`, covering every poll of a bulk operation.
+    pub(crate) fn bulk_wait(shop: &str, bulk_id: &str) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            inner: tracing::info_span!(
+                "shopify.bulk.wait",
+                shop,
+                bulk_id,
+                polls = tracing::field::Empty,
+                bulk_status = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+                http.request_id = tracing::field::Empty,
+                error = tracing::field::Empty,
+            ),
+        }
+    }
+
+    /// `shopify.token.refresh`. `grant` names the OAuth grant, never its
+    /// credentials.
+    pub(crate) fn token_refresh(shop: &str, grant: &'static str) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            inner: tracing::info_span!(
+                "shopify.token.refresh",
+                shop,
+                grant,
+                http.status = tracing::field::Empty,
+                retries = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+                http.request_id = tracing::field::Empty,
+                error = tracing::field::Empty,
+            ),
+        }
+    }
+
+    /// The span the current task runs in. Fields it does not declare are
+    /// ignored.
+    pub(crate) fn current() -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            inner: tracing::Span::current(),
+        }
+    }
+
+    pub(crate) async fn instrument<F: Future>(&self, future: F) -> F::Output {
+        #[cfg(feature = "tracing")]
+        return tracing::Instrument::instrument(future, self.inner.clone()).await;
+        #[cfg(not(feature = "tracing"))]
+        future.await
+    }
+
+    pub(crate) fn record_status(&self, status: StatusCode) {
+        #[cfg(feature = "tracing")]
+        self.inner.record("http.status", status.as_u16());
+    }
+
+    pub(crate) fn record_retries(&self, retries: u32) {
+        #[cfg(feature = "tracing")]
+        self.inner.record("retries", retries);
+    }
+
+    pub(crate) fn record_throttled_retries(&self, retries: u32) {
+        #[cfg(feature = "tracing")]
+        self.inner.record("throttled_retries", retries);
+    }
+
+    pub(crate) fn record_cost(&self, cost: Option<&QueryCost>) {
+        #[cfg(feature = "tracing")]
+        if let Some(cost) = cost {
+            self.inner.record(
+                "cost",
+                cost.actual_query_cost.unwrap_or(cost.requested_query_cost),
+            );
+            self.inner.record(
+                "throttle_available",
+                cost.throttle_status.currently_available,
+            );
+        }
+    }
+
+    pub(crate) fn record_bulk_poll(&self, polls: u32, status: &ShopifyBulkStatus) {
+        #[cfg(feature = "tracing")]
+        {
+            self.inner.record("polls", polls);
+            self.inner
+                .record("bulk_status", tracing::field::debug(status));
+        }
+    }
+
+    /// Records the latency and, when `result` failed, the kind of error with
+    /// its status and request id.
+    pub(crate) fn record_result<T>(
+        &self,
+        result: &Result<T, ShopifyAPIError>,
+        started_at: Instant,
+    ) {
+        #[cfg(feature = "tracing")]
+        {
+            self.inner
+                .record("latency_ms", started_at.elapsed().as_millis() as u64);
+            if let Err(err) = result {
+                if let Some(status) = err.status() {
+                    self.record_status(status);
+                }
+                if let Some(request_id) = err.request_id() {
+                    self.inner.record("http.request_id", request_id);
+                }
+                self.inner.record("error", error_kind(err));
+            }
+        }
+    }
+}
+
+/// A short name for the kind of `err`. Unlike its `Display`, it never quotes
+/// response bodies.
+#[cfg(feature = "tracing")]
+fn error_kind(err: &ShopifyAPIError) -> &'static str {
+    match err {
+        ShopifyAPIError::ConnectionFailed(err) if err.is_timeout() => "timeout",
+        ShopifyAPIError::ConnectionFailed(_) => "connection_failed",
+        ShopifyAPIError::Http { .. } => "http",
+        ShopifyAPIError::ResponseBroken => "response_broken",
+        ShopifyAPIError::NotJson(_) => "not_json",
+        ShopifyAPIError::NotWantedJsonFormat(_) => "unexpected_json",
+        ShopifyAPIError::Throttled => "throttled",
+        ShopifyAPIError::JsonParseError(_) | ShopifyAPIError::JsonlParseError { .. } => {
+            "json_parse"
+        }
+        ShopifyAPIError::Io(_) => "io",
+        ShopifyAPIError::InvalidApiVersion { .. } => "invalid_api_version",
+        ShopifyAPIError::Authentication(_) => "authentication",
+        ShopifyAPIError::InvalidAccessToken => "invalid_access_token",
+        ShopifyAPIError::InvalidOAuthCallback(_) => "invalid_oauth_callback",
+        ShopifyAPIError::InvalidSessionToken(_) => "invalid_session_token",
+        ShopifyAPIError::GraphqlErrors(_) => "graphql_errors",
+        ShopifyAPIError::MissingGraphqlData => "missing_graphql_data",
+        ShopifyAPIError::InvalidHeaderValue(_) => "invalid_header_value",
+        ShopifyAPIError::Timeout(_) => "timeout",
+        ShopifyAPIError::Other(_) => "other",
+    }
+}
+
+/// The name of the first operation defined in a GraphQL document, if it is
+/// named.
+pub(crate) fn operation_name(query: &str) -> Option<String> {
+    let mut depth = 0usize;
+    let mut word = String::new();
+    let mut after_keyword = false;
+    for line in query.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        for c in line.chars().chain([' ']) {
+            if depth == 0 && (c.is_alphanumeric() || c == '_') {
+                word.push(c);
+                continue;
+            }
+            if !word.is_empty() {
+                if after_keyword {
+                    return Some(word);
+                }
+                after_keyword = matches!(word.as_str(), "query" | "mutation" | "subscription");
+                word.clear();
+            }
+            if after_keyword && matches!(c, '{' | '(' | '@') {
+                return None;
+            }
+            match c {
+                '{' | '(' => depth += 1,
+                '}' | ')' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_operation_names() {
+        assert_eq!(
+            operation_name("query ShopName { shop { name } }").as_deref(),
+            Some("ShopName")
+        );
+        assert_eq!(
+            operation_name("# comment\nmutation\n  Update($id: ID!) { x }").as_deref(),
+            Some("Update")
+        );
+        assert_eq!(
+            operation_name("fragment F on Shop { id }\nquery Named { ...F }").as_deref(),
+            Some("Named")
+        );
+        assert_eq!(operation_name("query { shop { name } }"), None);
+        assert_eq!(
+            operation_name("query($id: ID!) { node(id: $id) { id } }"),
+            None
+        );
+        assert_eq!(operation_name("{ query { name } }"), None);
+    }
+}
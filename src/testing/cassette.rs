@@ -0,0 +1,459 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    transport::{HttpRequest, HttpResponse, HttpTransport, ReqwestTransport, TransportFuture},
+    ShopifyAPIError,
+};
+
+/// Keys containing any of these words, in any case, have their values
+/// replaced in recorded variables and responses.
+const REDACTED_KEY_PARTS: &[&str] = &["token", "secret", "password"];
+
+/// Response headers that are never recorded.
+const SKIPPED_HEADERS: &[&str] = &["set-cookie", "x-shopify-access-token"];
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Whether a [`CassetteTransport`] talks to Shopify or to its fixture file.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CassetteMode {
+    /// Sends requests to Shopify and appends every exchange to the file.
+    Record,
+    /// Answers from the file without touching the network.
+    Replay,
+}
+
+/// A VCR-style transport that records real traffic once and replays it in CI.
+///
+/// Requests are matched on method, URL, the SHA-256 of the GraphQL query
+/// (ignoring whitespace) and its variables. Identical requests are answered
+/// in the order they were recorded. Values of keys containing `token`,
+/// `secret` or `password` are redacted before anything is written, and
+/// request headers are not recorded at all.
+///
+/// ```rust,ignore
+/// let cassette = if std::env::var("RECORD").is_ok() {
+///     CassetteTransport::record("tests/fixtures/products.json")
+/// } else {
+///     CassetteTransport::replay("tests/fixtures/products.json")?
+/// };
+/// let shopify = Shopify::builder("my-shop", auth)
+///     .transport(Arc::new(cassette))
+///     .build()?;
+/// ```
+pub struct CassetteTransport {
+    path: PathBuf,
+    /// The recorded transport, `None` when replaying.
+    inner: Option<Arc<dyn HttpTransport>>,
+    state: Mutex<CassetteState>,
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl std::fmt::Debug for CassetteTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CassetteTransport")
+            .field("mode", &self.mode())
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Default)]
+struct CassetteState {
+    entries: Vec<CassetteEntry>,
+    replayed: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    entries: Vec<CassetteEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CassetteEntry {
+    method: String,
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    variables: Option<Value>,
+    response: CassetteResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CassetteResponse {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    json: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+impl CassetteTransport {
+    /// Records through a default [`ReqwestTransport`] into `path`, replacing
+    /// any previous recording.
+    pub fn record(path: impl AsRef<Path>) -> Self {
+        Self::record_with(path, Arc::new(ReqwestTransport::default()))
+    }
+
+    /// Records the traffic of `inner` into `path`.
+    pub fn record_with(path: impl AsRef<Path>, inner: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            inner: Some(inner),
+            state: Mutex::new(CassetteState::default()),
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Loads the recording at `path` for replay.
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self, ShopifyAPIError> {
+        let path = path.as_ref().to_path_buf();
+        let raw = std::fs::read(&path).map_err(|err| {
+            ShopifyAPIError::Other(format!("cannot read cassette {}: {err}", path.display()))
+        })?;
+        let file: CassetteFile = serde_json::from_slice(&raw)?;
+
+        Ok(Self {
+            path,
+            inner: None,
+            state: Mutex::new(CassetteState {
+                replayed: vec![false; file.entries.len()],
+                entries: file.entries,
+            }),
+            write_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        match self.inner {
+            Some(_) => CassetteMode::Record,
+            None => CassetteMode::Replay,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn state(&self) -> MutexGuard<'_, CassetteState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    async fn record_exchange(
+        &self,
+        inner: &dyn HttpTransport,
+        request: HttpRequest,
+    ) -> Result<HttpResponse, ShopifyAPIError> {
+        let mut entry = request_entry(&request);
+        let response = inner.send(request).await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+
+        entry.response = CassetteResponse::new(status, &headers, &body);
+
+        let _write = self.write_lock.lock().await;
+        let file = {
+            let mut state = self.state();
+            state.entries.push(entry);
+            CassetteFile {
+                entries: state.entries.clone(),
+            }
+        };
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(&file)?).await?;
+
+        Ok(HttpResponse::new(status, headers, body))
+    }
+
+    fn replay_exchange(&self, request: &HttpRequest) -> Result<HttpResponse, ShopifyAPIError> {
+        let wanted = request_entry(request);
+        let mut state = self.state();
+        let CassetteState { entries, replayed } = &mut *state;
+
+        let index = entries
+            .iter()
+            .zip(replayed.iter())
+            .position(|(entry, replayed)| !replayed && entry.matches(&wanted))
+            .ok_or_else(|| {
+                ShopifyAPIError::Other(format!(
+                    "no recorded response left in {} for {} {} (query hash: {})",
+                    self.path.display(),
+                    wanted.method,
+                    wanted.url,
+                    wanted.query_hash.as_deref().unwrap_or("none")
+                ))
+            })?;
+        replayed[index] = true;
+
+        entries[index].response.to_response()
+    }
+}
+
+impl HttpTransport for CassetteTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            match &self.inner {
+                Some(inner) => self.record_exchange(inner.as_ref(), request).await,
+                None => self.replay_exchange(&request),
+            }
+        })
+    }
+}
+
+impl CassetteEntry {
+    fn matches(&self, other: &CassetteEntry) -> bool {
+        self.method == other.method
+            && self.url == other.url
+            && self.query_hash == other.query_hash
+            && self.variables == other.variables
+    }
+}
+
+impl CassetteResponse {
+    fn new(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Self {
+        let headers = headers
+            .iter()
+            .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let (json, text) = match serde_json::from_slice::<Value>(body) {
+            Ok(mut json) => {
+                redact(&mut json);
+                (Some(json), None)
+            }
+            Err(_) => (None, Some(String::from_utf8_lossy(body).into_owned())),
+        };
+
+        Self {
+            status: status.as_u16(),
+            headers,
+            json,
+            text,
+        }
+    }
+
+    fn to_response(&self) -> Result<HttpResponse, ShopifyAPIError> {
+        let status = StatusCode::from_u16(self.status)
+            .map_err(|_| ShopifyAPIError::Other(format!("invalid status {}", self.status)))?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|err| ShopifyAPIError::Other(err.to_string()))?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        let body = match (&self.json, &self.text) {
+            (Some(json), _) => serde_json::to_vec(json)?,
+            (None, Some(text)) => text.clone().into_bytes(),
+            (None, None) => Vec::new(),
+        };
+
+        Ok(HttpResponse::new(status, headers, body))
+    }
+}
+
+/// The matching key of a request. Only GraphQL bodies are kept, as the hash
+/// of the query and the redacted variables.
+fn request_entry(request: &HttpRequest) -> CassetteEntry {
+    let graphql = serde_json::from_slice::<Value>(&request.body)
+        .ok()
+        .filter(|body| body["query"].is_string());
+    let (query_hash, variables) = match graphql {
+        Some(mut body) => {
+            let hash = query_hash(body["query"].as_str().unwrap_or_default());
+            let mut variables = body["variables"].take();
+            redact(&mut variables);
+            (Some(hash), Some(variables))
+        }
+        None => (None, None),
+    };
+
+    CassetteEntry {
+        method: request.method.to_string(),
+        url: request.url.clone(),
+        query_hash,
+        variables,
+        response: CassetteResponse {
+            status: 0,
+            headers: BTreeMap::new(),
+            json: None,
+            text: None,
+        },
+    }
+}
+
+/// SHA-256 of the query with whitespace collapsed, so reformatting a query
+/// does not invalidate its recording.
+fn query_hash(query: &str) -> String {
+    let normalized = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    Sha256::digest(normalized.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                // Objects such as `customerAccessToken { accessToken }` keep
+                // their shape, so replayed responses still deserialize.
+                if is_secret_key(key) && !matches!(value, Value::Null | Value::Object(_)) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    REDACTED_KEY_PARTS.iter().any(|part| key.contains(part))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        testing::{MockShopify, MOCK_BASE_URL},
+        Shopify, ShopifyAuth,
+    };
+
+    #[derive(Debug, Deserialize)]
+    struct ShopQuery {
+        shop: Value,
+    }
+
+    fn client(transport: Arc<dyn HttpTransport>) -> Shopify {
+        Shopify::builder(
+            "my-shop",
+            ShopifyAuth::client_credentials("client-id", "secret"),
+        )
+        .base_url(MOCK_BASE_URL)
+        .transport(transport)
+        .build()
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn records_redacted_traffic_and_replays_it_offline() {
+        let path = std::env::temp_dir()
+            .join(format!("shopify-api-cassette-{}", std::process::id()))
+            .join("shop.json");
+        let mock = MockShopify::new();
+        mock.respond("shop {", json!({"shop": {"name": "One"}}));
+        mock.respond("shop {", json!({"shop": {"name": "Two"}}));
+
+        let recorder = client(Arc::new(CassetteTransport::record_with(
+            &path,
+            Arc::new(mock.clone()),
+        )));
+        for _ in 0..2 {
+            recorder
+                .graphql::<ShopQuery, _>("query { shop { name } }", &json!({"id": 1}))
+                .await
+                .unwrap();
+        }
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("mock-token-1"));
+        assert!(!raw.contains("secret"));
+        assert!(raw.contains(REDACTED));
+
+        let player = client(Arc::new(CassetteTransport::replay(&path).unwrap()));
+        let names = [
+            player
+                .graphql::<ShopQuery, _>("query {\n  shop { name }\n}", &json!({"id": 1}))
+                .await
+                .unwrap(),
+            player
+                .graphql::<ShopQuery, _>("query { shop { name } }", &json!({"id": 1}))
+                .await
+                .unwrap(),
+        ]
+        .map(|data| data.shop["name"].clone());
+        assert_eq!(names, [json!("One"), json!("Two")]);
+        assert_eq!(mock.tokens_issued(), 1);
+
+        let missing = player
+            .graphql::<ShopQuery, _>("query { shop { name } }", &json!({"id": 2}))
+            .await;
+        assert!(matches!(missing, Err(ShopifyAPIError::Other(_))));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn redacts_passwords_and_tokens_in_any_case() {
+        let path = std::env::temp_dir()
+            .join(format!(
+                "shopify-api-cassette-secrets-{}",
+                std::process::id()
+            ))
+            .join("login.json");
+        let mock = MockShopify::new();
+        mock.respond(
+            "customerAccessTokenCreate",
+            json!({"customerAccessTokenCreate": {"customerAccessToken": {
+                "accessToken": "customer-token",
+                "expiresAt": "2026-01-01T00:00:00Z",
+            }}}),
+        );
+        let query = "mutation($input: CustomerAccessTokenCreateInput!) { \
+            customerAccessTokenCreate(input: $input) { customerAccessToken { accessToken } } }";
+        let variables = json!({"input": {
+            "email": "ada@example.com",
+            "password": "hunter2",
+            "Refresh_Token": "refresh-1",
+        }});
+
+        let recorder = client(Arc::new(CassetteTransport::record_with(
+            &path,
+            Arc::new(mock),
+        )));
+        recorder
+            .graphql::<Value, _>(query, &variables)
+            .await
+            .unwrap();
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        for secret in ["hunter2", "refresh-1", "customer-token"] {
+            assert!(!raw.contains(secret), "{secret} was recorded");
+        }
+        assert!(raw.contains("ada@example.com"));
+        assert!(raw.contains("2026-01-01T00:00:00Z"));
+
+        let player = client(Arc::new(CassetteTransport::replay(&path).unwrap()));
+        let data: Value = player.graphql(query, &variables).await.unwrap();
+        assert_eq!(
+            data["customerAccessTokenCreate"]["customerAccessToken"]["accessToken"],
+            REDACTED
+        );
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
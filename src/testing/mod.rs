@@ -0,0 +1,593 @@
+//! An in-process mock of the Shopify Admin API for offline tests.
+//!
+//! [`MockShopify`] is an [`HttpTransport`] that answers the requests of a
+//! [`Shopify`] client without opening sockets. It serves scripted GraphQL
+//! responses and simulates the token endpoint, throttling, bulk operations
+//! and staged uploads.
+//!
+//! ```rust,ignore
+//! let mock = MockShopify::new();
+//! mock.respond("shop {", json!({"shop": {"name": "Mock shop"}}));
+//! let shopify = mock.shopify(ShopifyAuth::AccessToken("token".to_string()))?;
+//! ```
+//!
+//! [`CassetteTransport`] records real traffic to a fixture file and replays it.
+
+mod cassette;
+
+pub use cassette::{CassetteMode, CassetteTransport};
+
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE, RETRY_AFTER},
+    Method, StatusCode,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    transport::{HttpRequest, HttpResponse, HttpTransport, TransportFuture},
+    Shopify, ShopifyAPIError, ShopifyAuth, ShopifyBuilder, ShopifyConfig,
+};
+
+/// Base URL the clients built by [`MockShopify`] send their requests to.
+pub const MOCK_BASE_URL: &str = "https://mock.shopify.test";
+
+/// A mock Shopify Admin API. Clones share the same state.
+#[derive(Clone, Default)]
+pub struct MockShopify {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl std::fmt::Debug for MockShopify {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockShopify").finish_non_exhaustive()
+    }
+}
+
+/// A file uploaded to a staged upload target.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MockStagedUpload {
+    /// The `key` parameter, which is also the staged upload path.
+    pub key: String,
+    pub file_name: Option<String>,
+    pub content: Vec<u8>,
+}
+
+/// A bulk operation started against the mock.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MockBulkOperation {
+    pub id: String,
+    /// The bulk query, or the mutation of a bulk mutation.
+    pub query: String,
+    pub staged_upload_path: Option<String>,
+    pub status: String,
+    results: String,
+    polls: u32,
+}
+
+struct ScriptedResponse {
+    matcher: String,
+    status: StatusCode,
+    body: Value,
+}
+
+struct MockState {
+    scripted: Vec<ScriptedResponse>,
+    requests: Vec<HttpRequest>,
+    revoked_tokens: HashSet<String>,
+    tokens_issued: u32,
+    token_lifetime: Option<i64>,
+    token_delay: Duration,
+    throttle_next: u32,
+    http_errors: VecDeque<(StatusCode, Option<Duration>)>,
+    bulk_results: VecDeque<String>,
+    bulk_operations: BTreeMap<u32, MockBulkOperation>,
+    bulk_polls_until_running: u32,
+    bulk_polls_until_completed: u32,
+    staged_uploads: Vec<MockStagedUpload>,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        Self {
+            scripted: Vec::new(),
+            requests: Vec::new(),
+            revoked_tokens: HashSet::new(),
+            tokens_issued: 0,
+            token_lifetime: Some(86_399),
+            token_delay: Duration::ZERO,
+            throttle_next: 0,
+            http_errors: VecDeque::new(),
+            bulk_results: VecDeque::new(),
+            bulk_operations: BTreeMap::new(),
+            bulk_polls_until_running: 1,
+            bulk_polls_until_completed: 2,
+            staged_uploads: Vec::new(),
+        }
+    }
+}
+
+impl MockShopify {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A builder for a client of `my-shop.myshopify.com` that talks to this
+    /// mock.
+    pub fn builder(&self, auth: ShopifyAuth) -> ShopifyBuilder {
+        Shopify::builder("my-shop", auth)
+            .transport(Arc::new(self.clone()))
+            .base_url(MOCK_BASE_URL)
+    }
+
+    pub fn shopify(&self, auth: ShopifyAuth) -> Result<Shopify, ShopifyAPIError> {
+        self.builder(auth).build()
+    }
+
+    pub fn shopify_with_config(
+        &self,
+        auth: ShopifyAuth,
+        config: ShopifyConfig,
+    ) -> Result<Shopify, ShopifyAPIError> {
+        self.builder(auth).config(config).build()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Answers the next GraphQL request whose query contains `matcher` with
+    /// `{"data": data}`. Each scripted response is used once, in the order
+    /// they were added, and takes precedence over the built-in handlers.
+    pub fn respond(&self, matcher: impl Into<String>, data: Value) {
+        self.respond_with(matcher, StatusCode::OK, json!({ "data": data }));
+    }
+
+    /// Answers the next GraphQL request whose query contains `matcher` with a
+    /// raw status and body, for example GraphQL `errors`.
+    pub fn respond_with(&self, matcher: impl Into<String>, status: StatusCode, body: Value) {
+        self.state().scripted.push(ScriptedResponse {
+            matcher: matcher.into(),
+            status,
+            body,
+        });
+    }
+
+    /// Answers the next `count` GraphQL requests with a `THROTTLED` error and
+    /// an empty cost bucket.
+    pub fn throttle_next(&self, count: u32) {
+        self.state().throttle_next += count;
+    }
+
+    /// Answers the next GraphQL request with an HTTP error, optionally with
+    /// `Retry-After`.
+    pub fn fail_next(&self, status: StatusCode, retry_after: Option<Duration>) {
+        self.state().http_errors.push_back((status, retry_after));
+    }
+
+    /// Makes GraphQL requests sent with `token` fail with `401 Unauthorized`.
+    pub fn revoke_token(&self, token: impl Into<String>) {
+        self.state().revoked_tokens.insert(token.into());
+    }
+
+    /// Sets the `expires_in` of issued tokens; `None` issues tokens without
+    /// expiry.
+    pub fn set_token_lifetime(&self, seconds: Option<i64>) {
+        self.state().token_lifetime = seconds;
+    }
+
+    /// Makes the token endpoint wait before answering, so that concurrent
+    /// callers overlap with a token request in flight.
+    pub fn delay_token_requests(&self, delay: Duration) {
+        self.state().token_delay = delay;
+    }
+
+    /// Number of tokens issued by the token endpoint.
+    pub fn tokens_issued(&self) -> u32 {
+        self.state().tokens_issued
+    }
+
+    /// Queues the JSONL result file of the next bulk operation.
+    pub fn push_bulk_result<I, T>(&self, rows: I)
+    where
+        I: IntoIterator<Item = T>,
+        T: serde::Serialize,
+    {
+        let jsonl = rows
+            .into_iter()
+            .map(|row| serde_json::to_string(&row).expect("bulk rows serialize to JSON"))
+            .map(|line| line + "\n")
+            .collect();
+        self.state().bulk_results.push_back(jsonl);
+    }
+
+    /// Sets after how many status polls bulk operations move from `CREATED`
+    /// to `RUNNING` and then to `COMPLETED`.
+    pub fn set_bulk_progress(&self, polls_until_running: u32, polls_until_completed: u32) {
+        let mut state = self.state();
+        state.bulk_polls_until_running = polls_until_running;
+        state.bulk_polls_until_completed = polls_until_completed.max(polls_until_running);
+    }
+
+    pub fn bulk_operations(&self) -> Vec<MockBulkOperation> {
+        self.state().bulk_operations.values().cloned().collect()
+    }
+
+    pub fn staged_uploads(&self) -> Vec<MockStagedUpload> {
+        self.state().staged_uploads.clone()
+    }
+
+    /// Every request received, in order.
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.state().requests.clone()
+    }
+
+    /// The JSON bodies of the GraphQL requests received, in order.
+    pub fn graphql_requests(&self) -> Vec<Value> {
+        self.state()
+            .requests
+            .iter()
+            .filter(|request| request.url.ends_with("/graphql.json"))
+            .filter_map(|request| serde_json::from_slice(&request.body).ok())
+            .collect()
+    }
+
+    fn handle(&self, request: HttpRequest) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let mut state = self.state();
+        state.requests.push(request.clone());
+
+        // Any host is accepted, so clients for several shops can share a mock.
+        let path = reqwest::Url::parse(&request.url)
+            .map(|url| url.path().to_string())
+            .unwrap_or_default();
+        match (&request.method, path.as_str()) {
+            (&Method::POST, "/admin/oauth/access_token") => state.token(&request),
+            (&Method::POST, path) if path.ends_with("/graphql.json") => state.graphql(&request),
+            (&Method::GET, path) if path.starts_with("/bulk/") => state.bulk_result(path),
+            (&Method::POST, "/staged-uploads") => state.staged_upload(&request),
+            _ => json_response(StatusCode::NOT_FOUND, json!({ "errors": "Not Found" })),
+        }
+    }
+}
+
+impl HttpTransport for MockShopify {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        let delay = match reqwest::Url::parse(&request.url) {
+            Ok(url) if url.path() == "/admin/oauth/access_token" => self.state().token_delay,
+            _ => Duration::ZERO,
+        };
+        Box::pin(async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            let (status, headers, body) = self.handle(request);
+            Ok(HttpResponse::new(status, headers, body))
+        })
+    }
+}
+
+impl MockState {
+    fn token(&mut self, request: &HttpRequest) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let form = form_fields(request.body_text().unwrap_or_default());
+        if !form.contains_key("client_id") {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                json!({ "error": "invalid_request" }),
+            );
+        }
+
+        self.tokens_issued += 1;
+        let issued = self.tokens_issued;
+        let mut token = json!({
+            "access_token": format!("mock-token-{issued}"),
+            "scope": "read_products,write_products",
+        });
+        if let Some(lifetime) = self.token_lifetime {
+            token["expires_in"] = lifetime.into();
+        }
+        if form.get("grant_type").map(String::as_str) == Some("refresh_token") {
+            token["refresh_token"] = format!("mock-refresh-{issued}").into();
+            token["refresh_token_expires_in"] = 7_776_000.into();
+        }
+
+        json_response(StatusCode::OK, token)
+    }
+
+    fn graphql(&mut self, request: &HttpRequest) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let token = [
+            "x-shopify-access-token",
+            "x-shopify-storefront-access-token",
+            "shopify-storefront-private-token",
+        ]
+        .into_iter()
+        .find_map(|name| request.headers.get(name))
+        .and_then(|token| token.to_str().ok());
+        match token {
+            Some(token) if !self.revoked_tokens.contains(token) => {}
+            _ => {
+                return json_response(
+                    StatusCode::UNAUTHORIZED,
+                    json!({ "errors": "[API] Invalid API key or access token" }),
+                )
+            }
+        }
+
+        if let Some((status, retry_after)) = self.http_errors.pop_front() {
+            let (status, mut headers, body) =
+                json_response(status, json!({ "errors": "Mock failure" }));
+            if let Some(retry_after) = retry_after {
+                headers.insert(RETRY_AFTER, HeaderValue::from(retry_after.as_secs()));
+            }
+            return (status, headers, body);
+        }
+
+        let body: Value = serde_json::from_slice(&request.body).unwrap_or_default();
+        let query = body["query"].as_str().unwrap_or_default();
+        let variables = &body["variables"];
+
+        if self.throttle_next > 0 {
+            self.throttle_next -= 1;
+            return json_response(
+                StatusCode::OK,
+                json!({
+                    "errors": [{
+                        "message": "Throttled",
+                        "extensions": { "code": "THROTTLED" }
+                    }],
+                    "extensions": { "cost": cost(10.0, 9.0) }
+                }),
+            );
+        }
+
+        if let Some(index) = self
+            .scripted
+            .iter()
+            .position(|scripted| query.contains(&scripted.matcher))
+        {
+            let scripted = self.scripted.remove(index);
+            let mut body = scripted.body;
+            if body.get("extensions").is_none() && body.is_object() {
+                body["extensions"] = json!({ "cost": cost(10.0, 990.0) });
+            }
+            return json_response(scripted.status, body);
+        }
+
+        let data = if query.contains("bulkOperationRunQuery(") {
+            let payload = self.start_bulk(variables["query"].as_str(), None);
+            json!({ "bulkOperationRunQuery": payload })
+        } else if query.contains("bulkOperationRunMutation(") {
+            let payload = self.start_bulk(
+                variables["mutation"].as_str(),
+                variables["stagedUploadPath"].as_str(),
+            );
+            json!({ "bulkOperationRunMutation": payload })
+        } else if query.contains("bulkOperation(id:") {
+            json!({ "bulkOperation": self.poll_bulk(variables["id"].as_str()) })
+        } else if query.contains("stagedUploadsCreate(") {
+            json!({ "stagedUploadsCreate": self.staged_targets(&variables["input"]) })
+        } else {
+            return json_response(
+                StatusCode::OK,
+                json!({
+                    "errors": [{ "message": format!("no mock response for query: {query}") }]
+                }),
+            );
+        };
+
+        json_response(
+            StatusCode::OK,
+            json!({ "data": data, "extensions": { "cost": cost(10.0, 990.0) } }),
+        )
+    }
+
+    fn start_bulk(&mut self, query: Option<&str>, staged_upload_path: Option<&str>) -> Value {
+        let number = self.bulk_operations.len() as u32 + 1;
+        let operation = MockBulkOperation {
+            id: format!("gid://shopify/BulkOperation/{number}"),
+            query: query.unwrap_or_default().to_string(),
+            staged_upload_path: staged_upload_path.map(str::to_string),
+            status: "CREATED".to_string(),
+            results: self.bulk_results.pop_front().unwrap_or_default(),
+            polls: 0,
+        };
+        let payload = json!({
+            "bulkOperation": bulk_json(number, &operation),
+            "userErrors": [],
+        });
+        self.bulk_operations.insert(number, operation);
+        payload
+    }
+
+    fn poll_bulk(&mut self, id: Option<&str>) -> Value {
+        let Some(number) = id
+            .and_then(|id| id.strip_prefix("gid://shopify/BulkOperation/"))
+            .and_then(|number| number.parse().ok())
+        else {
+            return Value::Null;
+        };
+        let (until_running, until_completed) = (
+            self.bulk_polls_until_running,
+            self.bulk_polls_until_completed,
+        );
+        let Some(operation) = self.bulk_operations.get_mut(&number) else {
+            return Value::Null;
+        };
+
+        operation.polls += 1;
+        operation.status = if operation.polls >= until_completed {
+            "COMPLETED"
+        } else if operation.polls >= until_running {
+            "RUNNING"
+        } else {
+            "CREATED"
+        }
+        .to_string();
+
+        bulk_json(number, operation)
+    }
+
+    fn bulk_result(&self, path: &str) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let operation = path
+            .strip_prefix("/bulk/")
+            .and_then(|name| name.strip_suffix(".jsonl"))
+            .and_then(|number| number.parse().ok())
+            .and_then(|number: u32| self.bulk_operations.get(&number))
+            .filter(|operation| operation.status == "COMPLETED");
+
+        match operation {
+            Some(operation) => (
+                StatusCode::OK,
+                HeaderMap::new(),
+                operation.results.clone().into_bytes(),
+            ),
+            None => json_response(StatusCode::NOT_FOUND, json!({ "errors": "Not Found" })),
+        }
+    }
+
+    fn staged_targets(&mut self, inputs: &Value) -> Value {
+        let targets = inputs
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, input)| {
+                let key = format!(
+                    "tmp/mock/{}/{}",
+                    self.staged_uploads.len() + index + 1,
+                    input["filename"].as_str().unwrap_or("upload")
+                );
+                json!({
+                    "url": format!("{MOCK_BASE_URL}/staged-uploads"),
+                    "resourceUrl": format!("{MOCK_BASE_URL}/staged-uploads/{key}"),
+                    "parameters": [
+                        { "name": "key", "value": key },
+                        { "name": "Content-Type", "value": input["mimeType"] },
+                    ],
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "stagedTargets": targets, "userErrors": [] })
+    }
+
+    fn staged_upload(&mut self, request: &HttpRequest) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let boundary = request
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split("boundary=").nth(1))
+            .map(str::to_string);
+        let Some(parts) = boundary.and_then(|boundary| parse_multipart(&request.body, &boundary))
+        else {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                json!({ "errors": "expected multipart/form-data" }),
+            );
+        };
+
+        let key = parts
+            .iter()
+            .find(|part| part.name == "key")
+            .map(|part| String::from_utf8_lossy(&part.content).into_owned());
+        let file = parts.into_iter().find(|part| part.name == "file");
+        let (Some(key), Some(file)) = (key, file) else {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                json!({ "errors": "missing key or file" }),
+            );
+        };
+
+        self.staged_uploads.push(MockStagedUpload {
+            key,
+            file_name: file.file_name,
+            content: file.content,
+        });
+        (StatusCode::NO_CONTENT, HeaderMap::new(), Vec::new())
+    }
+}
+
+fn bulk_json(number: u32, operation: &MockBulkOperation) -> Value {
+    let completed = operation.status == "COMPLETED";
+    json!({
+        "id": operation.id,
+        "status": operation.status,
+        "errorCode": null,
+        "createdAt": "2026-01-01T00:00:00Z",
+        "completedAt": completed.then_some("2026-01-01T00:01:00Z"),
+        "objectCount": completed.then(|| operation.results.lines().count().to_string()),
+        "fileSize": completed.then(|| operation.results.len().to_string()),
+        "url": completed.then(|| format!("{MOCK_BASE_URL}/bulk/{number}.jsonl")),
+        "partialDataUrl": null,
+    })
+}
+
+fn cost(requested: f64, available: f64) -> Value {
+    json!({
+        "requestedQueryCost": requested,
+        "actualQueryCost": requested,
+        "throttleStatus": {
+            "maximumAvailable": 1000.0,
+            "currentlyAvailable": available,
+            "restoreRate": 50.0,
+        },
+    })
+}
+
+fn json_response(status: StatusCode, body: Value) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert("x-request-id", HeaderValue::from_static("mock-request-id"));
+    (status, headers, body.to_string().into_bytes())
+}
+
+fn form_fields(body: &str) -> BTreeMap<String, String> {
+    reqwest::Url::parse(&format!("http://form.invalid/?{body}"))
+        .map(|url| url.query_pairs().into_owned().collect())
+        .unwrap_or_default()
+}
+
+struct MultipartField {
+    name: String,
+    file_name: Option<String>,
+    content: Vec<u8>,
+}
+
+fn parse_multipart(body: &[u8], boundary: &str) -> Option<Vec<MultipartField>> {
+    let delimiter = format!("--{boundary}");
+    let body = std::str::from_utf8(body).ok()?;
+    let mut fields = Vec::new();
+
+    for part in body.split(&delimiter).skip(1) {
+        if part.starts_with("--") {
+            break;
+        }
+        let part = part.strip_prefix("\r\n")?;
+        let (headers, content) = part.split_once("\r\n\r\n")?;
+        let content = content.strip_suffix("\r\n").unwrap_or(content);
+        let disposition = headers
+            .lines()
+            .find(|line| line.to_ascii_lowercase().starts_with("content-disposition"))?;
+
+        fields.push(MultipartField {
+            name: disposition_param(disposition, "name")?,
+            file_name: disposition_param(disposition, "filename"),
+            content: content.as_bytes().to_vec(),
+        });
+    }
+
+    Some(fields)
+}
+
+fn disposition_param(disposition: &str, param: &str) -> Option<String> {
+    disposition
+        .split(';')
+        .map(str::trim)
+        .find_map(|pair| pair.strip_prefix(&format!("{param}=\"")))
+        .and_then(|value| value.strip_suffix('"'))
+        .map(str::to_string)
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    collections::HashMap,
+    future::Future,
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use crate::{
+    graphql::{GraphqlResponse, QueryCost},
+    ShopifyAPIError,
+};
+
+/// How many distinct queries the limiter remembers the cost of. Clients that
+/// build queries dynamically would otherwise grow the map without bound.
+const MAX_KNOWN_QUERIES: usize = 1024;
+
+/// Client-side leaky-bucket limiter settings for the Admin GraphQL API.
+///
+/// The bucket mirrors `extensions.cost.throttleStatus` from each response and
+/// is shared by every clone of the `Shopify` client it was configured on.
+#[derive(Debug, Clone)]
+pub struct ThrottleConfig {
+    /// Cost assumed for a query until Shopify has reported its requested cost,
+    /// and for every new query once 1024 distinct queries are known.
+    pub default_query_cost: f64,
+    /// How many times a `THROTTLED` response is retried before it is returned.
+    pub max_retries: u32,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            default_query_cost: 50.0,
+            max_retries: 5,
+        }
+    }
+}
+
+/// A wait reported by [`CostLimiter::run`].
+pub(crate) enum ThrottleWait {
+    /// Waited for the bucket before sending.
+    Bucket(Duration),
+    /// Shopify answered `THROTTLED`; the request is replayed after `wait`.
+    Throttled { retries: u32, wait: Duration },
+}
+
+#[derive(Debug)]
+pub(crate) struct CostLimiter {
+    config: ThrottleConfig,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug, Default)]
+struct BucketState {
+    bucket: Option<Bucket>,
+    query_costs: HashMap<u64, f64>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    maximum: f64,
+    available: f64,
+    restore_rate: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.available = (self.available + elapsed * self.restore_rate).min(self.maximum);
+        self.updated_at = now;
+    }
+
+    fn wait_for(&self, cost: f64) -> Duration {
+        if self.available >= cost || self.restore_rate <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((cost - self.available) / self.restore_rate)
+    }
+}
+
+impl CostLimiter {
+    pub(crate) fn new(config: ThrottleConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(BucketState::default()),
+        }
+    }
+
+    /// Sends `query` once the bucket can afford it, replaying `THROTTLED`
+    /// responses up to [`ThrottleConfig::max_retries`] times. `on_wait` is
+    /// told about every wait, for metrics and spans.
+    pub(crate) async fn run<F, Fut>(
+        &self,
+        query: &str,
+        mut on_wait: impl FnMut(ThrottleWait),
+        mut send: F,
+    ) -> Result<GraphqlResponse<Value>, ShopifyAPIError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<GraphqlResponse<Value>, ShopifyAPIError>>,
+    {
+        let mut retries = 0;
+        loop {
+            let waited = self.acquire(query).await;
+            if !waited.is_zero() {
+                on_wait(ThrottleWait::Bucket(waited));
+            }
+            let response = send().await?;
+            self.observe(query, response.cost().as_ref());
+
+            if !response.is_throttled() || retries >= self.config.max_retries {
+                return Ok(response);
+            }
+
+            retries += 1;
+            let wait = self.throttled_wait(query);
+            log::debug!("shopify graphql request throttled, retrying in {wait:?}");
+            on_wait(ThrottleWait::Throttled { retries, wait });
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Waits until the bucket can afford `query`, then reserves its cost.
+    /// Returns how long it waited.
+    async fn acquire(&self, query: &str) -> Duration {
+        let mut waited = Duration::ZERO;
+        while let Some(wait) = self.reserve(query) {
+            tokio::time::sleep(wait).await;
+            waited += wait;
+        }
+        waited
+    }
+
+    /// Reserves the estimated cost of `query`, or returns how long to wait
+    /// before trying again.
+    fn reserve(&self, query: &str) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        let cost = state
+            .query_costs
+            .get(&query_key(query))
+            .copied()
+            .unwrap_or(self.config.default_query_cost);
+        let bucket = state.bucket.as_mut()?;
+
+        bucket.refill(Instant::now());
+        // A query costing more than the whole bucket can never be afforded;
+        // let Shopify reject it instead of waiting forever.
+        let cost = cost.min(bucket.maximum);
+        let wait = bucket.wait_for(cost);
+        if wait.is_zero() {
+            bucket.available -= cost;
+            None
+        } else {
+            Some(wait)
+        }
+    }
+
+    /// Updates the bucket from a response's `extensions.cost`.
+    fn observe(&self, query: &str, cost: Option<&QueryCost>) {
+        let Some(cost) = cost else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+
+        let key = query_key(query);
+        if state.query_costs.len() < MAX_KNOWN_QUERIES || state.query_costs.contains_key(&key) {
+            state.query_costs.insert(key, cost.requested_query_cost);
+        }
+        state.bucket = Some(Bucket {
+            maximum: cost.throttle_status.maximum_available,
+            available: cost.throttle_status.currently_available,
+            restore_rate: cost.throttle_status.restore_rate,
+            updated_at: Instant::now(),
+        });
+    }
+
+    /// How long to wait before replaying a query Shopify rejected as throttled.
+    fn throttled_wait(&self, query: &str) -> Duration {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        let cost = state
+            .query_costs
+            .get(&query_key(query))
+            .copied()
+            .unwrap_or(self.config.default_query_cost);
+
+        match state.bucket.as_mut() {
+            Some(bucket) => {
+                bucket.refill(Instant::now());
+                bucket.wait_for(cost.min(bucket.maximum))
+            }
+            None => Duration::from_secs(1),
+        }
+    }
+}
+
+fn query_key(query: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    query.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::ThrottleStatus;
+
+    #[test]
+    fn limiter_waits_for_the_restore_rate_to_cover_known_costs() {
+        let limiter = CostLimiter::new(ThrottleConfig::default());
+        assert_eq!(limiter.reserve("query"), None);
+
+        limiter.observe(
+            "query",
+            Some(&QueryCost {
+                requested_query_cost: 110.0,
+                actual_query_cost: None,
+                throttle_status: ThrottleStatus {
+                    maximum_available: 2000.0,
+                    currently_available: 10.0,
+                    restore_rate: 100.0,
+                },
+            }),
+        );
+
+        let wait = limiter.reserve("query").unwrap();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        assert!(limiter.throttled_wait("query") <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn limiter_remembers_a_bounded_number_of_query_costs() {
+        let limiter = CostLimiter::new(ThrottleConfig::default());
+        let cost = QueryCost {
+            requested_query_cost: 10.0,
+            actual_query_cost: None,
+            throttle_status: ThrottleStatus {
+                maximum_available: 2000.0,
+                currently_available: 2000.0,
+                restore_rate: 100.0,
+            },
+        };
+
+        for id in 0..MAX_KNOWN_QUERIES + 10 {
+            limiter.observe(&format!("{{ product(id: {id}) {{ id }} }}"), Some(&cost));
+        }
+
+        let state = limiter.state.lock().unwrap();
+        assert_eq!(state.query_costs.len(), MAX_KNOWN_QUERIES);
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    future::Future,
+    hash::{BuildHasher, RandomState},
+    pin::Pin,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Method, StatusCode,
+};
+
+use crate::ShopifyAPIError;
+
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<HttpResponse, ShopifyAPIError>> + Send + 'a>>;
+
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, ShopifyAPIError>> + Send>>;
+
+/// Sends the HTTP requests of a [`Shopify`](crate::Shopify) client.
+///
+/// [`ReqwestTransport`] is used unless another transport is set with
+/// [`ShopifyBuilder::transport`](crate::ShopifyBuilder::transport), for
+/// example a fake that answers without opening sockets.
+pub trait HttpTransport: Send + Sync {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_>;
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub timeout: Option<Duration>,
+}
+
+impl HttpRequest {
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            timeout: None,
+        }
+    }
+
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new(Method::GET, url)
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Self::new(Method::POST, url)
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn json<T>(self, body: &T) -> Result<Self, ShopifyAPIError>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let body = serde_json::to_vec(body)?;
+        Ok(self
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .with_body(body))
+    }
+
+    /// Sets an `application/x-www-form-urlencoded` body.
+    pub fn form(self, fields: &[(&str, &str)]) -> Self {
+        let mut url = reqwest::Url::parse("http://form.invalid/").expect("static URL is valid");
+        url.query_pairs_mut().extend_pairs(fields);
+        let body = url.query().unwrap_or_default().to_string();
+
+        self.header(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        )
+        .with_body(body)
+    }
+
+    pub fn multipart(self, form: MultipartForm) -> Self {
+        let (content_type, body) = form.encode();
+        self.header(CONTENT_TYPE, content_type).with_body(body)
+    }
+
+    pub fn with_body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// The body as UTF-8, when it is text.
+    pub fn body_text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.body).ok()
+    }
+}
+
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    body: BodyStream,
+}
+
+impl std::fmt::Debug for HttpResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpResponse {
+    pub fn new(status: StatusCode, headers: HeaderMap, body: impl Into<Bytes>) -> Self {
+        let body = body.into();
+        Self::from_stream(status, headers, stream::once(async move { Ok(body) }))
+    }
+
+    pub fn from_stream<S>(status: StatusCode, headers: HeaderMap, body: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, ShopifyAPIError>> + Send + 'static,
+    {
+        Self {
+            status,
+            headers,
+            body: Box::pin(body),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+
+    pub async fn bytes(self) -> Result<Bytes, ShopifyAPIError> {
+        let chunks = self.body.try_collect::<Vec<_>>().await?;
+        Ok(chunks.concat().into())
+    }
+
+    pub async fn text(self) -> Result<String, ShopifyAPIError> {
+        let bytes = self.bytes().await?;
+        String::from_utf8(bytes.into()).map_err(|_| ShopifyAPIError::ResponseBroken)
+    }
+
+    pub async fn json<T>(self) -> Result<T, ShopifyAPIError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let bytes = self.bytes().await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    pub fn bytes_stream(self) -> BodyStream {
+        self.body
+    }
+}
+
+/// The default transport, backed by a [`reqwest::Client`].
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let mut builder = self
+                .client
+                .request(request.method, &request.url)
+                .headers(request.headers)
+                .body(request.body);
+            if let Some(timeout) = request.timeout {
+                builder = builder.timeout(timeout);
+            }
+
+            let response = builder.send().await?;
+            let status = response.status();
+            let headers = response.headers().clone();
+            let body = response
+                .bytes_stream()
+                .map(|chunk| chunk.map_err(ShopifyAPIError::ConnectionFailed));
+
+            Ok(HttpResponse::from_stream(status, headers, body))
+        })
+    }
+}
+
+/// A `multipart/form-data` body.
+///
+/// It is encoded into [`HttpRequest::body`], so every transport, including
+/// fakes and cassettes, sends and sees the same bytes.
+#[derive(Debug, Clone, Default)]
+pub struct MultipartForm {
+    parts: Vec<MultipartPart>,
+}
+
+#[derive(Debug, Clone)]
+struct MultipartPart {
+    name: String,
+    file_name: Option<String>,
+    content_type: Option<String>,
+    data: Bytes,
+}
+
+impl MultipartForm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parts.push(MultipartPart {
+            name: name.into(),
+            file_name: None,
+            content_type: None,
+            data: Bytes::from(value.into()),
+        });
+        self
+    }
+
+    pub fn file(
+        mut self,
+        name: impl Into<String>,
+        file_name: impl Into<String>,
+        content_type: impl Into<String>,
+        data: impl Into<Bytes>,
+    ) -> Self {
+        self.parts.push(MultipartPart {
+            name: name.into(),
+            file_name: Some(file_name.into()),
+            content_type: Some(content_type.into()),
+            data: data.into(),
+        });
+        self
+    }
+
+    fn encode(&self) -> (HeaderValue, Bytes) {
+        let boundary = format!(
+            "shopify-api-{:016x}{:016x}",
+            RandomState::new().hash_one(self.parts.len()),
+            RandomState::new().hash_one("boundary")
+        );
+        let mut body = Vec::new();
+
+        for part in &self.parts {
+            body.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+            let name = escape_quoted(&part.name);
+            match &part.file_name {
+                Some(file_name) => body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{name}\"; filename=\"{}\"\r\n",
+                        escape_quoted(file_name)
+                    )
+                    .as_bytes(),
+                ),
+                None => body.extend_from_slice(
+                    format!("Content-Disposition: form-data; name=\"{name}\"\r\n").as_bytes(),
+                ),
+            }
+            if let Some(content_type) = &part.content_type {
+                body.extend_from_slice(format!("Content-Type: {content_type}\r\n").as_bytes());
+            }
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(&part.data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+
+        let content_type =
+            HeaderValue::from_str(&format!("multipart/form-data; boundary={boundary}"))
+                .expect("boundary is a valid header value");
+        (content_type, body.into())
+    }
+}
+
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_form_and_multipart_bodies() {
+        let form = HttpRequest::post("https://example.test").form(&[("a", "1 2"), ("b", "&")]);
+        assert_eq!(form.body_text(), Some("a=1+2&b=%26"));
+
+        let multipart = HttpRequest::post("https://example.test").multipart(
+            MultipartForm::new().text("key", "tmp/upload").file(
+                "file",
+                "vars.jsonl",
+                "text/jsonl",
+                "{}\n",
+            ),
+        );
+        let content_type = multipart.headers[CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type.split("boundary=").nth(1).unwrap();
+        let body = multipart.body_text().unwrap();
+
+        assert!(body.starts_with(&format!("--{boundary}\r\n")));
+        assert!(body.contains("name=\"key\"\r\n\r\ntmp/upload\r\n"));
+        assert!(
+            body.contains("filename=\"vars.jsonl\"\r\nContent-Type: text/jsonl\r\n\r\n{}\n\r\n")
+        );
+        assert!(body.ends_with(&format!("--{boundary}--\r\n")));
+    }
+}
@@ -1,4 +1,51 @@
-use std::future::Future;
+use std::{future::Future, time::Duration};
+
+use crate::{transport::HttpResponse, ShopifyAPIError};
+
+/// Turns a non-2xx response into [`ShopifyAPIError::Http`], keeping the body,
+/// the `X-Request-Id` header and `Retry-After`.
+pub(crate) async fn error_for_status(
+    response: HttpResponse,
+) -> Result<HttpResponse, ShopifyAPIError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let headers = response.headers();
+    let request_id = headers
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let retry_after = headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    let body = response.text().await.unwrap_or_default();
+
+    Err(ShopifyAPIError::Http {
+        status,
+        body,
+        request_id,
+        retry_after,
+    })
+}
+
+/// Parses a `Retry-After` value given either in seconds or as an HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        // Values too large for a `Duration` are ignored rather than trusted.
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
 
 /// Retry function for async functions
 /// # Example
@@ -17,9 +64,11 @@ use std::future::Future;
 /// }
 /// ```
 /// # Errors
-/// This function returns an error if the async function returns an error
-/// # Panics
-/// This function panics if the number of retries is 0
+/// This function returns an error if the async function returns an error.
+/// A `max_retries` of 0 runs the function once, like 1.
+///
+/// Retries happen immediately; requests made through [`crate::Shopify`] are
+/// retried with backoff according to [`crate::RetryPolicy`] instead.
 pub async fn retry_async<'a, Fut, F, Args, Out, ErrOut>(
     max_retries: u64,
     func: Fut,
@@ -30,14 +79,10 @@ where
     F: Future<Output = Result<Out, ErrOut>>,
     ErrOut: std::fmt::Debug,
 {
-    if max_retries == 0 {
-        panic!("Max retries cannot be 0");
-    }
-
     let mut count: u64 = 0;
     let mut result: Result<Out, ErrOut> = func(args).await;
 
-    while count < max_retries - 1 && result.is_err() {
+    while count + 1 < max_retries && result.is_err() {
         let executed_func = func(args);
 
         result = executed_func.await;
@@ -67,9 +112,8 @@ where
 /// }
 /// ```
 /// # Errors
-/// This function returns an error if the sync function returns an error
-/// # Panics
-/// This function panics if the number of retries is 0
+/// This function returns an error if the sync function returns an error.
+/// A `max_retries` of 0 runs the function once, like 1.
 pub fn retry_sync<'a, F, Args, Out, ErrOut>(
     max_retries: u64,
     func: F,
@@ -79,14 +123,10 @@ where
     F: Fn(&'a Args) -> Result<Out, ErrOut>,
     ErrOut: std::fmt::Debug,
 {
-    if max_retries == 0 {
-        panic!("Max retries cannot be 0");
-    }
-
     let mut count: u64 = 0;
     let mut result: Result<Out, ErrOut> = func(args);
 
-    while count < max_retries - 1 && result.is_err() {
+    while count + 1 < max_retries && result.is_err() {
         let executed_func = func(args);
 
         result = executed_func;
@@ -100,6 +140,23 @@ where
     result
 }
 
+#[cfg(feature = "oauth")]
+pub(crate) fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(feature = "oauth")]
+pub(crate) fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
 #[derive(Clone, Debug)]
 pub enum ReadJsonTreeError {
     JsonNotFound,
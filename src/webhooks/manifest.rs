@@ -0,0 +1,390 @@
+use std::{collections::BTreeSet, fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::WebhookSubscription;
+use crate::{Shopify, ShopifyAPIError};
+
+/// The webhook subscriptions an app expects a shop to have.
+///
+/// Loadable from JSON, or from TOML with the `webhook-manifest-toml` feature:
+///
+/// ```toml
+/// [[webhooks]]
+/// topic = "orders/create"
+/// uri = "https://example.com/webhooks/orders"
+/// include_fields = ["id", "total_price"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookManifest {
+    #[serde(default)]
+    pub webhooks: Vec<WebhookDefinition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookDefinition {
+    /// `ORDERS_CREATE` or `orders/create`.
+    pub topic: String,
+    #[serde(alias = "endpoint")]
+    pub uri: String,
+    #[serde(default = "default_format")]
+    pub format: String,
+    #[serde(default)]
+    pub filter: Option<String>,
+    #[serde(default)]
+    pub include_fields: Vec<String>,
+    #[serde(default)]
+    pub metafield_namespaces: Vec<String>,
+}
+
+fn default_format() -> String {
+    "JSON".to_string()
+}
+
+impl WebhookDefinition {
+    pub fn new(topic: impl Into<String>, uri: impl Into<String>) -> Self {
+        Self {
+            topic: topic.into(),
+            uri: uri.into(),
+            format: default_format(),
+            filter: None,
+            include_fields: Vec::new(),
+            metafield_namespaces: Vec::new(),
+        }
+    }
+
+    /// The topic as a `WebhookSubscriptionTopic` enum value.
+    pub fn topic_enum(&self) -> String {
+        self.topic.trim().replace('/', "_").to_uppercase()
+    }
+
+    pub(crate) fn format_enum(&self) -> String {
+        self.format.trim().to_uppercase()
+    }
+
+    fn matches(&self, subscription: &WebhookSubscription) -> bool {
+        subscription.topic == self.topic_enum() && subscription.uri == self.uri
+    }
+
+    /// Describes how `subscription` differs from this definition.
+    fn differences(&self, subscription: &WebhookSubscription) -> Vec<String> {
+        let mut differences = Vec::new();
+        if subscription.format != self.format_enum() {
+            differences.push(format!(
+                "format: {} -> {}",
+                subscription.format,
+                self.format_enum()
+            ));
+        }
+        if subscription.filter.as_deref().unwrap_or_default()
+            != self.filter.as_deref().unwrap_or_default()
+        {
+            differences.push(format!(
+                "filter: {:?} -> {:?}",
+                subscription.filter, self.filter
+            ));
+        }
+        if as_set(&subscription.include_fields) != as_set(&self.include_fields) {
+            differences.push(format!(
+                "include_fields: {:?} -> {:?}",
+                subscription.include_fields, self.include_fields
+            ));
+        }
+        if as_set(&subscription.metafield_namespaces) != as_set(&self.metafield_namespaces) {
+            differences.push(format!(
+                "metafield_namespaces: {:?} -> {:?}",
+                subscription.metafield_namespaces, self.metafield_namespaces
+            ));
+        }
+        differences
+    }
+}
+
+fn as_set(values: &[String]) -> BTreeSet<&str> {
+    values.iter().map(String::as_str).collect()
+}
+
+impl WebhookManifest {
+    pub fn from_json_str(json: &str) -> Result<Self, ShopifyAPIError> {
+        serde_json::from_str(json).map_err(ShopifyAPIError::JsonParseError)
+    }
+
+    #[cfg(feature = "webhook-manifest-toml")]
+    pub fn from_toml_str(toml: &str) -> Result<Self, ShopifyAPIError> {
+        toml::from_str(toml).map_err(|err| ShopifyAPIError::Other(err.to_string()))
+    }
+
+    /// Reads a manifest from disk, parsing `.toml` files as TOML and anything
+    /// else as JSON.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ShopifyAPIError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            #[cfg(feature = "webhook-manifest-toml")]
+            return Self::from_toml_str(&contents);
+            #[cfg(not(feature = "webhook-manifest-toml"))]
+            return Err(ShopifyAPIError::Other(
+                "TOML manifests require the `webhook-manifest-toml` feature".to_string(),
+            ));
+        }
+
+        Self::from_json_str(&contents)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WebhookSyncOptions {
+    /// Compute and log the plan without applying it.
+    pub dry_run: bool,
+    /// Leave subscriptions that are not in the manifest untouched instead of
+    /// deleting them.
+    pub keep_unmanaged: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookAction {
+    Create(WebhookDefinition),
+    Update {
+        existing: WebhookSubscription,
+        desired: WebhookDefinition,
+    },
+    Delete(WebhookSubscription),
+    /// Matches the manifest already.
+    Unchanged(WebhookSubscription),
+    /// Not in the manifest, kept because of [`WebhookSyncOptions::keep_unmanaged`].
+    Unmanaged(WebhookSubscription),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebhookPlan {
+    pub actions: Vec<WebhookAction>,
+}
+
+impl WebhookPlan {
+    /// Diffs the manifest against the shop's current subscriptions.
+    ///
+    /// Subscriptions are matched on topic and URI; a match whose other fields
+    /// differ is updated in place. Extra subscriptions for the same topic and
+    /// URI are treated as unmanaged.
+    pub fn new(
+        manifest: &WebhookManifest,
+        existing: Vec<WebhookSubscription>,
+        options: WebhookSyncOptions,
+    ) -> Self {
+        let mut existing = existing.into_iter().map(Some).collect::<Vec<_>>();
+        let mut actions = Vec::new();
+
+        for desired in &manifest.webhooks {
+            let matched = existing
+                .iter_mut()
+                .find(|subscription| {
+                    subscription
+                        .as_ref()
+                        .is_some_and(|subscription| desired.matches(subscription))
+                })
+                .and_then(Option::take);
+
+            actions.push(match matched {
+                None => WebhookAction::Create(desired.clone()),
+                Some(subscription) if desired.differences(&subscription).is_empty() => {
+                    WebhookAction::Unchanged(subscription)
+                }
+                Some(subscription) => WebhookAction::Update {
+                    existing: subscription,
+                    desired: desired.clone(),
+                },
+            });
+        }
+
+        actions.extend(existing.into_iter().flatten().map(|subscription| {
+            if options.keep_unmanaged {
+                WebhookAction::Unmanaged(subscription)
+            } else {
+                WebhookAction::Delete(subscription)
+            }
+        }));
+
+        Self { actions }
+    }
+
+    /// Whether applying the plan would change anything.
+    pub fn has_changes(&self) -> bool {
+        self.actions.iter().any(|action| {
+            matches!(
+                action,
+                WebhookAction::Create(_) | WebhookAction::Update { .. } | WebhookAction::Delete(_)
+            )
+        })
+    }
+}
+
+impl fmt::Display for WebhookPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mut create, mut update, mut delete) = (0, 0, 0);
+
+        for action in &self.actions {
+            match action {
+                WebhookAction::Create(desired) => {
+                    create += 1;
+                    writeln!(f, "+ create {} -> {}", desired.topic_enum(), desired.uri)?;
+                }
+                WebhookAction::Update { existing, desired } => {
+                    update += 1;
+                    writeln!(
+                        f,
+                        "~ update {} -> {} ({})",
+                        existing.topic,
+                        existing.uri,
+                        desired.differences(existing).join(", ")
+                    )?;
+                }
+                WebhookAction::Delete(existing) => {
+                    delete += 1;
+                    writeln!(f, "- delete {} -> {}", existing.topic, existing.uri)?;
+                }
+                WebhookAction::Unchanged(existing) => {
+                    writeln!(f, "  keep {} -> {}", existing.topic, existing.uri)?;
+                }
+                WebhookAction::Unmanaged(existing) => {
+                    writeln!(f, "  keep unmanaged {} -> {}", existing.topic, existing.uri)?;
+                }
+            }
+        }
+
+        write!(
+            f,
+            "{create} to create, {update} to update, {delete} to delete"
+        )
+    }
+}
+
+impl Shopify {
+    /// Computes the actions needed to make the shop match `manifest`.
+    pub async fn plan_webhooks(
+        &self,
+        manifest: &WebhookManifest,
+        options: WebhookSyncOptions,
+    ) -> Result<WebhookPlan, ShopifyAPIError> {
+        let existing = self.list_webhooks().await?;
+        Ok(WebhookPlan::new(manifest, existing, options))
+    }
+
+    /// Runs the create, update and delete actions of `plan`.
+    pub async fn apply_webhook_plan(&self, plan: &WebhookPlan) -> Result<(), ShopifyAPIError> {
+        for action in &plan.actions {
+            match action {
+                WebhookAction::Create(desired) => {
+                    self.create_webhook(desired).await?;
+                }
+                WebhookAction::Update { existing, desired } => {
+                    self.update_webhook(&existing.id, desired).await?;
+                }
+                WebhookAction::Delete(existing) => self.delete_webhook(&existing.id).await?,
+                WebhookAction::Unchanged(_) | WebhookAction::Unmanaged(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Plans and, unless `options.dry_run` is set, applies `manifest`.
+    /// Returns the plan either way.
+    pub async fn sync_webhooks(
+        &self,
+        manifest: &WebhookManifest,
+        options: WebhookSyncOptions,
+    ) -> Result<WebhookPlan, ShopifyAPIError> {
+        let plan = self.plan_webhooks(manifest, options).await?;
+        log::info!("webhook plan for {}:\n{plan}", self.shop_domain());
+
+        if !options.dry_run {
+            self.apply_webhook_plan(&plan).await?;
+        }
+
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(id: &str, topic: &str, uri: &str) -> WebhookSubscription {
+        WebhookSubscription {
+            id: id.to_string(),
+            topic: topic.to_string(),
+            format: "JSON".to_string(),
+            uri: uri.to_string(),
+            include_fields: Vec::new(),
+            metafield_namespaces: Vec::new(),
+            filter: None,
+            api_version: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn plan_creates_updates_in_place_and_deletes_unmanaged() {
+        let manifest = WebhookManifest::from_json_str(
+            r#"{"webhooks": [
+                {"topic": "orders/create", "endpoint": "https://a.test/orders"},
+                {"topic": "PRODUCTS_UPDATE", "uri": "https://a.test/products", "include_fields": ["id"]},
+                {"topic": "app/uninstalled", "uri": "https://a.test/uninstalled"}
+            ]}"#,
+        )
+        .unwrap();
+        let existing = vec![
+            subscription("1", "ORDERS_CREATE", "https://a.test/orders"),
+            subscription("2", "PRODUCTS_UPDATE", "https://a.test/products"),
+            subscription("3", "CUSTOMERS_CREATE", "https://old.test"),
+        ];
+
+        let plan = WebhookPlan::new(&manifest, existing.clone(), WebhookSyncOptions::default());
+
+        assert!(matches!(plan.actions[0], WebhookAction::Unchanged(ref s) if s.id == "1"));
+        assert!(
+            matches!(plan.actions[1], WebhookAction::Update { ref existing, .. } if existing.id == "2")
+        );
+        assert!(matches!(plan.actions[2], WebhookAction::Create(_)));
+        assert!(matches!(plan.actions[3], WebhookAction::Delete(ref s) if s.id == "3"));
+        assert!(plan
+            .to_string()
+            .ends_with("1 to create, 1 to update, 1 to delete"));
+
+        let keep = WebhookPlan::new(
+            &manifest,
+            existing,
+            WebhookSyncOptions {
+                keep_unmanaged: true,
+                ..WebhookSyncOptions::default()
+            },
+        );
+        assert!(matches!(keep.actions[3], WebhookAction::Unmanaged(_)));
+    }
+
+    #[cfg(feature = "webhook-manifest-toml")]
+    #[test]
+    fn parses_toml_manifest() {
+        let manifest = WebhookManifest::from_toml_str(
+            r#"
+            [[webhooks]]
+            topic = "orders/create"
+            uri = "https://a.test/orders"
+            filter = "total_price:>100"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.webhooks[0].topic_enum(), "ORDERS_CREATE");
+        assert_eq!(manifest.webhooks[0].format, "JSON");
+        assert_eq!(
+            manifest.webhooks[0].filter.as_deref(),
+            Some("total_price:>100")
+        );
+    }
+}
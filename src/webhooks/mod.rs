@@ -1,13 +1,40 @@
 pub mod frameworks;
+pub mod manifest;
 pub mod verify;
 pub mod webhook;
 
 use serde::{Deserialize, Serialize};
 
+pub use manifest::{
+    WebhookAction, WebhookDefinition, WebhookManifest, WebhookPlan, WebhookSyncOptions,
+};
+
 #[derive(Debug, Deserialize, Serialize, Clone, Eq, PartialEq)]
 pub struct WebhookSubscription {
     pub id: String,
     pub topic: String,
     pub format: String,
     pub uri: String,
+    #[serde(default)]
+    pub include_fields: Vec<String>,
+    #[serde(default)]
+    pub metafield_namespaces: Vec<String>,
+    #[serde(default)]
+    pub filter: Option<String>,
+    #[serde(default)]
+    pub api_version: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+/// Narrows [`crate::Shopify::list_webhooks_filtered`] and
+/// [`crate::Shopify::webhooks_stream`]. Empty fields match every subscription.
+#[derive(Debug, Clone, Default)]
+pub struct WebhooksFilter {
+    pub topics: Vec<String>,
+    pub uri: Option<String>,
+    /// Page size, `250` when zero.
+    pub first: u32,
 }
@@ -1,30 +1,34 @@
-use super::WebhookSubscription;
-use crate::{Shopify, ShopifyAPIError};
-use serde::Deserialize;
+use super::{
+    manifest::{WebhookDefinition, WebhookManifest, WebhookSyncOptions},
+    WebhookSubscription, WebhooksFilter,
+};
+use crate::{graphql::PaginateOptions, utils::ReadJsonTreeSteps, Shopify, ShopifyAPIError};
+use futures_util::{Stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-#[derive(Debug, Deserialize)]
-struct WebhookSubscriptionsData {
-    #[serde(rename = "webhookSubscriptions")]
-    webhook_subscriptions: WebhookSubscriptionConnection,
-}
-
-#[derive(Debug, Deserialize)]
-struct WebhookSubscriptionConnection {
-    edges: Vec<WebhookSubscriptionEdge>,
-}
-
-#[derive(Debug, Deserialize)]
-struct WebhookSubscriptionEdge {
-    node: WebhookSubscriptionNode,
-}
-
 #[derive(Debug, Deserialize)]
 struct WebhookSubscriptionNode {
     id: String,
     topic: String,
     format: String,
     uri: String,
+    #[serde(rename = "includeFields")]
+    include_fields: Option<Vec<String>>,
+    #[serde(rename = "metafieldNamespaces")]
+    metafield_namespaces: Option<Vec<String>>,
+    filter: Option<String>,
+    #[serde(rename = "apiVersion")]
+    api_version: Option<WebhookApiVersion>,
+    #[serde(rename = "createdAt")]
+    created_at: Option<String>,
+    #[serde(rename = "updatedAt")]
+    updated_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookApiVersion {
+    handle: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,6 +37,12 @@ struct WebhookCreateData {
     webhook_subscription_create: WebhookMutationPayload,
 }
 
+#[derive(Debug, Deserialize)]
+struct WebhookUpdateData {
+    #[serde(rename = "webhookSubscriptionUpdate")]
+    webhook_subscription_update: WebhookMutationPayload,
+}
+
 #[derive(Debug, Deserialize)]
 struct WebhookDeleteData {
     #[serde(rename = "webhookSubscriptionDelete")]
@@ -47,6 +57,46 @@ struct WebhookMutationPayload {
     user_errors: Vec<WebhookUserError>,
 }
 
+impl WebhookMutationPayload {
+    fn into_subscription(
+        self,
+        mutation_name: &str,
+    ) -> Result<WebhookSubscription, ShopifyAPIError> {
+        if !self.user_errors.is_empty() {
+            return Err(ShopifyAPIError::Other(format!(
+                "{mutation_name} returned errors: {:?}",
+                self.user_errors
+            )));
+        }
+
+        self.webhook_subscription
+            .map(Into::into)
+            .ok_or_else(|| ShopifyAPIError::Other("no webhook subscription returned".to_string()))
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookInput<'a> {
+    uri: &'a str,
+    format: String,
+    filter: &'a str,
+    include_fields: &'a [String],
+    metafield_namespaces: &'a [String],
+}
+
+impl<'a> From<&'a WebhookDefinition> for WebhookInput<'a> {
+    fn from(definition: &'a WebhookDefinition) -> Self {
+        Self {
+            uri: &definition.uri,
+            format: definition.format_enum(),
+            filter: definition.filter.as_deref().unwrap_or_default(),
+            include_fields: &definition.include_fields,
+            metafield_namespaces: &definition.metafield_namespaces,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct WebhookDeletePayload {
     #[serde(rename = "deletedWebhookSubscriptionId")]
@@ -69,38 +119,80 @@ impl From<WebhookSubscriptionNode> for WebhookSubscription {
             topic: node.topic,
             format: node.format,
             uri: node.uri,
+            include_fields: node.include_fields.unwrap_or_default(),
+            metafield_namespaces: node.metafield_namespaces.unwrap_or_default(),
+            filter: node.filter,
+            api_version: node.api_version.map(|version| version.handle),
+            created_at: node.created_at,
+            updated_at: node.updated_at,
         }
     }
 }
 
 impl Shopify {
+    /// Lists every webhook subscription of the shop, following pagination.
     pub async fn list_webhooks(&self) -> Result<Vec<WebhookSubscription>, ShopifyAPIError> {
-        let data: WebhookSubscriptionsData = self
-            .graphql(
-                r#"
-                query webhookSubscriptions {
-                    webhookSubscriptions(first: 250) {
-                        edges {
-                            node {
-                                id
-                                topic
-                                format
-                                uri
-                            }
+        self.list_webhooks_filtered(WebhooksFilter::default()).await
+    }
+
+    /// Lists every webhook subscription matching `filter`, following
+    /// pagination.
+    pub async fn list_webhooks_filtered(
+        &self,
+        filter: WebhooksFilter,
+    ) -> Result<Vec<WebhookSubscription>, ShopifyAPIError> {
+        self.webhooks_stream(filter).try_collect().await
+    }
+
+    /// Streams the webhook subscriptions matching `filter` page by page.
+    pub fn webhooks_stream(
+        &self,
+        filter: WebhooksFilter,
+    ) -> impl Stream<Item = Result<WebhookSubscription, ShopifyAPIError>> + Send + 'static {
+        let first = if filter.first == 0 { 250 } else { filter.first };
+        let topics = (!filter.topics.is_empty()).then_some(filter.topics);
+
+        self.paginate::<WebhookSubscriptionNode, _>(
+            r#"
+            query webhookSubscriptions(
+                $first: Int!,
+                $after: String,
+                $topics: [WebhookSubscriptionTopic!],
+                $uri: String
+            ) {
+                webhookSubscriptions(first: $first, after: $after, topics: $topics, uri: $uri) {
+                    nodes {
+                        id
+                        topic
+                        format
+                        uri
+                        includeFields
+                        metafieldNamespaces
+                        filter
+                        apiVersion {
+                            handle
                         }
+                        createdAt
+                        updatedAt
+                    }
+                    pageInfo {
+                        hasNextPage
+                        endCursor
                     }
                 }
-                "#,
-                &json!({}),
-            )
-            .await?;
-
-        Ok(data
-            .webhook_subscriptions
-            .edges
-            .into_iter()
-            .map(|edge| edge.node.into())
-            .collect())
+            }
+            "#,
+            &json!({
+                "topics": topics,
+                "uri": filter.uri,
+            }),
+            &[ReadJsonTreeSteps::Key("webhookSubscriptions")],
+            PaginateOptions {
+                page_size: Some(first),
+                ..PaginateOptions::default()
+            },
+        )
+        .map_ok(Into::into)
     }
 
     pub async fn add_webhook(
@@ -108,27 +200,42 @@ impl Shopify {
         address: &str,
         topic: &str,
         format: &str,
+    ) -> Result<WebhookSubscription, ShopifyAPIError> {
+        self.create_webhook(&WebhookDefinition {
+            format: format.to_string(),
+            ..WebhookDefinition::new(topic, address)
+        })
+        .await
+    }
+
+    pub async fn create_webhook(
+        &self,
+        definition: &WebhookDefinition,
     ) -> Result<WebhookSubscription, ShopifyAPIError> {
         let data: WebhookCreateData = self
             .graphql(
                 r#"
                 mutation webhookSubscriptionCreate(
                     $topic: WebhookSubscriptionTopic!,
-                    $uri: String!,
-                    $format: WebhookSubscriptionFormat!
+                    $webhookSubscription: WebhookSubscriptionInput!
                 ) {
                     webhookSubscriptionCreate(
                         topic: $topic,
-                        webhookSubscription: {
-                            uri: $uri,
-                            format: $format
-                        }
+                        webhookSubscription: $webhookSubscription
                     ) {
                         webhookSubscription {
                             id
                             topic
                             format
                             uri
+                            includeFields
+                            metafieldNamespaces
+                            filter
+                            apiVersion {
+                                handle
+                            }
+                            createdAt
+                            updatedAt
                         }
                         userErrors {
                             field
@@ -138,24 +245,64 @@ impl Shopify {
                 }
                 "#,
                 &json!({
-                    "topic": topic,
-                    "uri": address,
-                    "format": format,
+                    "topic": definition.topic_enum(),
+                    "webhookSubscription": WebhookInput::from(definition),
                 }),
             )
             .await?;
 
-        if !data.webhook_subscription_create.user_errors.is_empty() {
-            return Err(ShopifyAPIError::Other(format!(
-                "webhookSubscriptionCreate returned errors: {:?}",
-                data.webhook_subscription_create.user_errors
-            )));
-        }
-
         data.webhook_subscription_create
-            .webhook_subscription
-            .map(Into::into)
-            .ok_or_else(|| ShopifyAPIError::Other("no webhook subscription returned".to_string()))
+            .into_subscription("webhookSubscriptionCreate")
+    }
+
+    /// Updates a subscription in place through `webhookSubscriptionUpdate`.
+    /// The topic of a subscription cannot change.
+    pub async fn update_webhook(
+        &self,
+        webhook_id: &str,
+        definition: &WebhookDefinition,
+    ) -> Result<WebhookSubscription, ShopifyAPIError> {
+        let data: WebhookUpdateData = self
+            .graphql(
+                r#"
+                mutation webhookSubscriptionUpdate(
+                    $id: ID!,
+                    $webhookSubscription: WebhookSubscriptionInput!
+                ) {
+                    webhookSubscriptionUpdate(
+                        id: $id,
+                        webhookSubscription: $webhookSubscription
+                    ) {
+                        webhookSubscription {
+                            id
+                            topic
+                            format
+                            uri
+                            includeFields
+                            metafieldNamespaces
+                            filter
+                            apiVersion {
+                                handle
+                            }
+                            createdAt
+                            updatedAt
+                        }
+                        userErrors {
+                            field
+                            message
+                        }
+                    }
+                }
+                "#,
+                &json!({
+                    "id": webhook_id,
+                    "webhookSubscription": WebhookInput::from(definition),
+                }),
+            )
+            .await?;
+
+        data.webhook_subscription_update
+            .into_subscription("webhookSubscriptionUpdate")
     }
 
     pub async fn delete_webhook(&self, webhook_id: &str) -> Result<(), ShopifyAPIError> {
@@ -189,29 +336,69 @@ impl Shopify {
             .ok_or_else(|| ShopifyAPIError::Other("webhook was not deleted".to_string()))
     }
 
+    /// Makes the shop's subscriptions match `desired_webhooks`, given as
+    /// `(address, topic, format)` tuples, deleting every other subscription.
+    ///
+    /// See [`Shopify::sync_webhooks`] for filters, include fields, dry runs and
+    /// keeping unmanaged subscriptions.
     pub async fn webhook_auto_config(
         &self,
         desired_webhooks: Vec<(&str, &str, &str)>,
     ) -> Result<(), ShopifyAPIError> {
-        let existing_webhooks = self.list_webhooks().await?;
-        let mut desired = desired_webhooks;
-
-        for webhook in &existing_webhooks {
-            let matches_desired = desired.iter().position(|(address, topic, format)| {
-                webhook.uri == *address && webhook.topic == *topic && webhook.format == *format
-            });
-
-            if let Some(index) = matches_desired {
-                desired.remove(index);
-            } else {
-                self.delete_webhook(&webhook.id).await?;
-            }
-        }
-
-        for (address, topic, format) in desired {
-            self.add_webhook(address, topic, format).await?;
-        }
+        let manifest = WebhookManifest {
+            webhooks: desired_webhooks
+                .into_iter()
+                .map(|(address, topic, format)| WebhookDefinition {
+                    format: format.to_string(),
+                    ..WebhookDefinition::new(topic, address)
+                })
+                .collect(),
+        };
 
+        self.sync_webhooks(&manifest, WebhookSyncOptions::default())
+            .await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhook_node_flattens_api_version_and_defaults_lists() {
+        let node: WebhookSubscriptionNode = serde_json::from_value(json!({
+            "id": "gid://shopify/WebhookSubscription/1",
+            "topic": "ORDERS_CREATE",
+            "format": "JSON",
+            "uri": "https://example.com/webhooks",
+            "includeFields": null,
+            "filter": "total_price:>100",
+            "apiVersion": {"handle": "2026-04"},
+            "createdAt": "2026-04-01T00:00:00Z",
+            "updatedAt": null
+        }))
+        .unwrap();
+
+        let subscription = WebhookSubscription::from(node);
+
+        assert_eq!(subscription.api_version.as_deref(), Some("2026-04"));
+        assert_eq!(subscription.filter.as_deref(), Some("total_price:>100"));
+        assert!(subscription.include_fields.is_empty());
+        assert!(subscription.metafield_namespaces.is_empty());
+    }
+
+    #[test]
+    fn subscriptions_saved_without_the_new_fields_still_deserialize() {
+        let subscription: WebhookSubscription = serde_json::from_value(json!({
+            "id": "gid://shopify/WebhookSubscription/1",
+            "topic": "ORDERS_CREATE",
+            "format": "JSON",
+            "uri": "https://example.com/webhooks"
+        }))
+        .unwrap();
+
+        assert!(subscription.include_fields.is_empty());
+        assert_eq!(subscription.filter, None);
+    }
+}
@@ -129,7 +129,7 @@ async fn live_bulk_query_wait_list_and_download_when_env_is_present() -> Result<
     assert_eq!(operation.status, ShopifyBulkStatus::Completed);
 
     if let Some(url) = operation.url {
-        let rows: Vec<serde_json::Value> = Shopify::download_bulk_jsonl(&url).await?;
+        let rows: Vec<serde_json::Value> = shopify.collect_bulk_jsonl(&url).await?;
         for row in rows {
             assert!(row.get("id").is_some());
         }
@@ -139,7 +139,7 @@ async fn live_bulk_query_wait_list_and_download_when_env_is_present() -> Result<
 }
 
 #[tokio::test]
-async fn live_five_concurrent_bulk_queries_when_enabled() -> Result<(), ShopifyAPIError> {
+async fn live_scheduled_bulk_queries_when_enabled() -> Result<(), ShopifyAPIError> {
     if std::env::var("SHOPIFY_TEST_BULK_CONCURRENCY")
         .ok()
         .as_deref()
@@ -162,51 +162,22 @@ async fn live_five_concurrent_bulk_queries_when_enabled() -> Result<(), ShopifyA
             }
         }
     }"#;
-    let payloads = shopify
-        .run_bulk_queries(vec![query; 5], BulkConcurrencyOptions::default())
-        .await?;
-    let operations = payloads
-        .into_iter()
-        .map(|payload| {
-            assert!(
-                payload.user_errors.is_empty(),
-                "bulk query returned user errors: {:?}",
-                payload.user_errors
-            );
-            payload
-                .bulk_operation
-                .expect("bulkOperationRunQuery should return a bulk operation")
-        })
-        .collect::<Vec<_>>();
-
-    assert_eq!(operations.len(), 5);
-
-    let running_or_recent = shopify
-        .list_bulk_operations(BulkOperationsFilter {
-            first: 10,
-            query: Some("type:QUERY".to_string()),
-            ..BulkOperationsFilter::default()
-        })
-        .await?;
-    for operation in &operations {
-        assert!(running_or_recent
-            .edges
-            .iter()
-            .any(|edge| edge.node.id == operation.id));
-    }
-
-    for operation in operations {
-        let operation = shopify
-            .wait_for_bulk(
-                &operation.id,
-                BulkWaitOptions {
+    let operations = shopify
+        .run_bulk_queries(
+            vec![query; 12],
+            BulkConcurrencyOptions {
+                wait: BulkWaitOptions {
                     poll_interval: Duration::from_secs(5),
                     timeout: Some(Duration::from_secs(180)),
                 },
-            )
-            .await?;
+                ..BulkConcurrencyOptions::default()
+            },
+        )
+        .await;
 
-        assert_eq!(operation.status, ShopifyBulkStatus::Completed);
+    assert_eq!(operations.len(), 12);
+    for operation in operations {
+        assert_eq!(operation?.status, ShopifyBulkStatus::Completed);
     }
 
     Ok(())
@@ -0,0 +1,891 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::Utc;
+use futures_util::StreamExt;
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    StatusCode,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use shopify_api::{
+    testing::MockShopify,
+    transport::{HttpRequest, HttpResponse},
+    BulkWaitOptions, GraphqlRequest, GraphqlResponse, MemoryTokenStore, MetricLabels,
+    MiddlewareFuture, PoolOptions, QueryCost, RetryPolicy, ShopifyAPIError, ShopifyAuth,
+    ShopifyBulkStatus, ShopifyConfig, ShopifyMetrics, ShopifyMiddleware, ShopifyPool,
+    ThrottleConfig, TokenData, TokenStore,
+};
+
+#[derive(Debug, Deserialize)]
+struct ShopQuery {
+    shop: Shop,
+}
+
+#[derive(Debug, Deserialize)]
+struct Shop {
+    name: String,
+}
+
+const SHOP_QUERY: &str = "query { shop { name } }";
+
+fn fast_wait() -> BulkWaitOptions {
+    BulkWaitOptions {
+        poll_interval: Duration::from_millis(1),
+        timeout: Some(Duration::from_secs(5)),
+    }
+}
+
+fn fast_retry() -> RetryPolicy {
+    RetryPolicy {
+        initial_delay: Duration::from_millis(1),
+        jitter: false,
+        ..RetryPolicy::default()
+    }
+}
+
+#[tokio::test]
+async fn serves_scripted_graphql_responses() {
+    let mock = MockShopify::new();
+    mock.respond("shop {", json!({"shop": {"name": "Mock shop"}}));
+    let shopify = mock
+        .shopify(ShopifyAuth::AccessToken("token".to_string()))
+        .unwrap();
+
+    let data: ShopQuery = shopify.graphql(SHOP_QUERY, &json!({})).await.unwrap();
+
+    assert_eq!(data.shop.name, "Mock shop");
+    assert_eq!(mock.graphql_requests()[0]["query"], SHOP_QUERY);
+}
+
+#[tokio::test]
+async fn pagination_fails_when_the_end_cursor_repeats() {
+    let mock = MockShopify::new();
+    for _ in 0..3 {
+        mock.respond(
+            "products(",
+            json!({"products": {
+                "nodes": [{"id": 1}],
+                "pageInfo": {"hasNextPage": true, "endCursor": "same"}
+            }}),
+        );
+    }
+    let shopify = mock
+        .shopify(ShopifyAuth::AccessToken("token".to_string()))
+        .unwrap();
+
+    let items = shopify
+        .paginate::<serde_json::Value, _>(
+            "query($after: String) { products(first: 1, after: $after) { nodes { id } pageInfo { hasNextPage endCursor } } }",
+            &json!({}),
+            &[shopify_api::utils::ReadJsonTreeSteps::Key("products")],
+            Default::default(),
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(items.len(), 3);
+    assert!(items[..2].iter().all(Result::is_ok));
+    assert!(matches!(items[2], Err(ShopifyAPIError::Other(_))));
+    assert_eq!(mock.graphql_requests().len(), 2);
+}
+
+#[tokio::test]
+async fn client_credentials_token_is_requested_once_and_reused() {
+    let mock = MockShopify::new();
+    mock.respond("shop {", json!({"shop": {"name": "One"}}));
+    mock.respond("shop {", json!({"shop": {"name": "Two"}}));
+    let shopify = mock
+        .shopify(ShopifyAuth::client_credentials("client-id", "secret"))
+        .unwrap();
+
+    for _ in 0..2 {
+        shopify
+            .graphql::<ShopQuery, _>(SHOP_QUERY, &json!({}))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(mock.tokens_issued(), 1);
+    assert_eq!(shopify.access_token().await.unwrap(), "mock-token-1");
+}
+
+#[tokio::test]
+async fn expired_offline_token_is_refreshed_once_for_concurrent_requests() {
+    let mock = MockShopify::new();
+    // Keeps the first refresh in flight while the other requests start.
+    mock.delay_token_requests(Duration::from_millis(50));
+    for _ in 0..10 {
+        mock.respond("shop {", json!({"shop": {"name": "Mock shop"}}));
+    }
+    let expired = TokenData {
+        expires_at: Some(Utc::now() - chrono::Duration::minutes(1)),
+        refresh_token: Some("refresh".to_string()),
+        ..TokenData::never_expiring("expired")
+    };
+    let shopify = mock
+        .shopify(ShopifyAuth::expiring_offline_token(
+            "client-id",
+            "secret",
+            expired,
+        ))
+        .unwrap();
+
+    let variables = json!({});
+    let results = futures_util::future::join_all(
+        (0..10).map(|_| shopify.graphql::<ShopQuery, _>(SHOP_QUERY, &variables)),
+    )
+    .await;
+
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(mock.tokens_issued(), 1);
+}
+
+#[tokio::test]
+async fn revoked_token_is_refreshed_and_request_replayed() {
+    let mock = MockShopify::new();
+    mock.respond("shop {", json!({"shop": {"name": "Mock shop"}}));
+    let shopify = mock
+        .shopify(ShopifyAuth::client_credentials("client-id", "secret"))
+        .unwrap();
+    shopify.access_token().await.unwrap();
+    mock.revoke_token("mock-token-1");
+
+    let data: ShopQuery = shopify.graphql(SHOP_QUERY, &json!({})).await.unwrap();
+
+    assert_eq!(data.shop.name, "Mock shop");
+    assert_eq!(mock.tokens_issued(), 2);
+
+    let static_client = mock
+        .shopify(ShopifyAuth::AccessToken("mock-token-1".to_string()))
+        .unwrap();
+    assert!(matches!(
+        static_client
+            .graphql::<ShopQuery, _>(SHOP_QUERY, &json!({}))
+            .await,
+        Err(ShopifyAPIError::InvalidAccessToken)
+    ));
+}
+
+#[tokio::test]
+async fn throttled_and_failed_requests_are_retried() {
+    let mock = MockShopify::new();
+    mock.respond("shop {", json!({"shop": {"name": "Mock shop"}}));
+    mock.throttle_next(1);
+    mock.fail_next(StatusCode::SERVICE_UNAVAILABLE, Some(Duration::ZERO));
+    let shopify = mock
+        .shopify_with_config(
+            ShopifyAuth::AccessToken("token".to_string()),
+            ShopifyConfig {
+                throttle: Some(ThrottleConfig::default()),
+                retry: fast_retry(),
+                ..ShopifyConfig::default()
+            },
+        )
+        .unwrap();
+
+    let data: ShopQuery = shopify.graphql(SHOP_QUERY, &json!({})).await.unwrap();
+
+    assert_eq!(data.shop.name, "Mock shop");
+    assert_eq!(mock.graphql_requests().len(), 3);
+}
+
+#[tokio::test]
+async fn throttled_requests_are_retried_without_a_limiter() {
+    let mock = MockShopify::new();
+    mock.respond("shop {", json!({"shop": {"name": "Mock shop"}}));
+    mock.throttle_next(2);
+    let shopify = mock
+        .shopify_with_config(
+            ShopifyAuth::AccessToken("token".to_string()),
+            ShopifyConfig {
+                retry: fast_retry(),
+                ..ShopifyConfig::default()
+            },
+        )
+        .unwrap();
+
+    let data: ShopQuery = shopify.graphql(SHOP_QUERY, &json!({})).await.unwrap();
+
+    assert_eq!(data.shop.name, "Mock shop");
+    assert_eq!(mock.graphql_requests().len(), 3);
+}
+
+#[tokio::test]
+async fn http_errors_keep_status_and_request_id() {
+    let mock = MockShopify::new();
+    mock.fail_next(StatusCode::BAD_GATEWAY, None);
+    let shopify = mock
+        .shopify_with_config(
+            ShopifyAuth::AccessToken("token".to_string()),
+            ShopifyConfig {
+                retry: RetryPolicy::none(),
+                ..ShopifyConfig::default()
+            },
+        )
+        .unwrap();
+
+    let err = shopify
+        .graphql::<ShopQuery, _>(SHOP_QUERY, &json!({}))
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.status(), Some(StatusCode::BAD_GATEWAY));
+    assert_eq!(err.request_id(), Some("mock-request-id"));
+    assert!(err.is_retryable());
+}
+
+#[tokio::test]
+async fn bulk_query_progresses_to_completed_and_streams_results() {
+    let mock = MockShopify::new();
+    mock.push_bulk_result([
+        json!({"id": "gid://shopify/Product/1"}),
+        json!({"id": "gid://shopify/Product/2"}),
+    ]);
+    let shopify = mock
+        .shopify(ShopifyAuth::AccessToken("token".to_string()))
+        .unwrap();
+
+    let payload = shopify
+        .run_bulk_query("{ products { edges { node { id } } } }")
+        .await
+        .unwrap();
+    let operation = payload.bulk_operation.unwrap();
+    assert_eq!(operation.status, ShopifyBulkStatus::Created);
+
+    let completed = shopify
+        .wait_for_bulk(&operation.id, fast_wait())
+        .await
+        .unwrap();
+    assert_eq!(completed.status, ShopifyBulkStatus::Completed);
+
+    let url = completed.url.unwrap();
+    let rows = futures_util::StreamExt::collect::<Vec<_>>(
+        shopify
+            .stream_bulk_jsonl::<serde_json::Value>(&url)
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].as_ref().unwrap()["id"], "gid://shopify/Product/2");
+
+    let collected: Vec<serde_json::Value> = shopify.collect_bulk_jsonl(&url).await.unwrap();
+    assert_eq!(collected.len(), 2);
+    assert_eq!(mock.requests().last().unwrap().url, url);
+}
+
+#[tokio::test]
+async fn staged_upload_feeds_a_bulk_mutation() {
+    let mock = MockShopify::new();
+    let shopify = mock
+        .shopify(ShopifyAuth::AccessToken("token".to_string()))
+        .unwrap();
+
+    let path = shopify
+        .stage_upload_jsonl(&[
+            json!({"input": {"title": "A"}}),
+            json!({"input": {"title": "B"}}),
+        ])
+        .await
+        .unwrap();
+    let uploads = mock.staged_uploads();
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[0].key, path);
+    assert_eq!(
+        String::from_utf8(uploads[0].content.clone()).unwrap(),
+        "{\"input\":{\"title\":\"A\"}}\n{\"input\":{\"title\":\"B\"}}"
+    );
+
+    let payload = shopify
+        .run_bulk_mutation("mutation call($input: ProductInput!) { productCreate(input: $input) { product { id } } }", &path)
+        .await
+        .unwrap();
+    let completed = shopify
+        .wait_for_bulk(&payload.bulk_operation.unwrap().id, fast_wait())
+        .await
+        .unwrap();
+
+    assert_eq!(completed.status, ShopifyBulkStatus::Completed);
+    assert_eq!(
+        mock.bulk_operations()[0].staged_upload_path.as_deref(),
+        Some(path.as_str())
+    );
+}
+
+#[derive(Default)]
+struct RecordedMetrics(Mutex<Vec<String>>);
+
+impl RecordedMetrics {
+    fn push(&self, metric: String) {
+        self.0.lock().unwrap().push(metric);
+    }
+}
+
+impl ShopifyMetrics for RecordedMetrics {
+    fn request(&self, labels: &MetricLabels<'_>, _: Duration, error: Option<&ShopifyAPIError>) {
+        self.push(format!(
+            "request {} error={}",
+            labels.operation,
+            error.is_some()
+        ));
+    }
+
+    fn throttle_wait(&self, labels: &MetricLabels<'_>, _: Duration) {
+        self.push(format!("throttle_wait {}", labels.operation));
+    }
+
+    fn query_cost(&self, labels: &MetricLabels<'_>, cost: &QueryCost) {
+        self.push(format!(
+            "query_cost {} {}",
+            labels.operation, cost.requested_query_cost
+        ));
+    }
+
+    fn bulk_operation(&self, labels: &MetricLabels<'_>, status: &ShopifyBulkStatus, _: Duration) {
+        self.push(format!("bulk_operation {} {status:?}", labels.operation));
+    }
+
+    fn token_refresh(
+        &self,
+        labels: &MetricLabels<'_>,
+        _: Duration,
+        error: Option<&ShopifyAPIError>,
+    ) {
+        assert_eq!(labels.shop, "my-shop.myshopify.com");
+        self.push(format!(
+            "token_refresh {} error={}",
+            labels.operation,
+            error.is_some()
+        ));
+    }
+}
+
+#[tokio::test]
+async fn metrics_observe_requests_throttling_and_bulk_operations() {
+    let mock = MockShopify::new();
+    mock.respond("shop {", json!({"shop": {"name": "Mock shop"}}));
+    mock.throttle_next(1);
+    let metrics = Arc::new(RecordedMetrics::default());
+    let shopify = mock
+        .shopify_with_config(
+            ShopifyAuth::client_credentials("client-id", "secret"),
+            ShopifyConfig {
+                throttle: Some(ThrottleConfig::default()),
+                metrics: Some(metrics.clone()),
+                ..ShopifyConfig::default()
+            },
+        )
+        .unwrap();
+
+    shopify
+        .graphql::<ShopQuery, _>("query ShopName { shop { name } }", &json!({}))
+        .await
+        .unwrap();
+    let payload = shopify
+        .run_bulk_query("{ products { edges { node { id } } } }")
+        .await
+        .unwrap();
+    shopify
+        .wait_for_bulk(&payload.bulk_operation.unwrap().id, fast_wait())
+        .await
+        .unwrap();
+
+    let recorded = metrics.0.lock().unwrap();
+    assert_eq!(
+        recorded[..4],
+        [
+            "token_refresh client_credentials error=false",
+            "throttle_wait ShopName",
+            "query_cost ShopName 10",
+            "request ShopName error=false",
+        ]
+    );
+    assert!(recorded.contains(&"bulk_operation bulk_operation Completed".to_string()));
+}
+
+/// Tags every HTTP request, blocks mutations, answers cached queries and
+/// hides the shop name.
+#[derive(Default)]
+struct Guard {
+    sent: Mutex<Vec<String>>,
+}
+
+impl ShopifyMiddleware for Guard {
+    fn before_graphql<'a>(
+        &'a self,
+        request: &'a mut GraphqlRequest,
+    ) -> MiddlewareFuture<'a, Option<GraphqlResponse<serde_json::Value>>> {
+        Box::pin(async move {
+            if request.is_mutation() {
+                return Err(ShopifyAPIError::Other("read-only".to_string()));
+            }
+            if request.operation_name().as_deref() == Some("Cached") {
+                return Ok(Some(serde_json::from_value(
+                    json!({"data": {"shop": {"name": "Cached shop"}}}),
+                )?));
+            }
+            Ok(None)
+        })
+    }
+
+    fn after_graphql<'a>(
+        &'a self,
+        _request: &'a GraphqlRequest,
+        response: &'a mut GraphqlResponse<serde_json::Value>,
+    ) -> MiddlewareFuture<'a> {
+        Box::pin(async move {
+            if let Some(data) = &mut response.data {
+                data["shop"]["name"] = json!("[hidden]");
+            }
+            Ok(())
+        })
+    }
+
+    fn before_request<'a>(
+        &'a self,
+        request: &'a mut HttpRequest,
+    ) -> MiddlewareFuture<'a, Option<HttpResponse>> {
+        Box::pin(async move {
+            request.headers.insert(
+                HeaderName::from_static("x-audit"),
+                HeaderValue::from_static("yes"),
+            );
+            self.sent.lock().unwrap().push(request.url.clone());
+            Ok(None)
+        })
+    }
+}
+
+#[tokio::test]
+async fn middleware_modifies_blocks_and_answers_requests() {
+    let mock = MockShopify::new();
+    mock.respond("shop {", json!({"shop": {"name": "Mock shop"}}));
+    let guard = Arc::new(Guard::default());
+    let shopify = mock
+        .shopify_with_config(
+            ShopifyAuth::client_credentials("client-id", "secret"),
+            ShopifyConfig {
+                middleware: vec![guard.clone()],
+                ..ShopifyConfig::default()
+            },
+        )
+        .unwrap();
+
+    let data: ShopQuery = shopify.graphql(SHOP_QUERY, &json!({})).await.unwrap();
+    assert_eq!(data.shop.name, "[hidden]");
+
+    let cached: ShopQuery = shopify
+        .graphql("query Cached { shop { name } }", &json!({}))
+        .await
+        .unwrap();
+    assert_eq!(cached.shop.name, "[hidden]");
+
+    let blocked = shopify
+        .graphql::<serde_json::Value, _>("mutation { shopUpdate { id } }", &json!({}))
+        .await;
+    assert!(matches!(blocked, Err(ShopifyAPIError::Other(_))));
+
+    assert_eq!(
+        *guard.sent.lock().unwrap(),
+        [
+            "https://mock.shopify.test/admin/oauth/access_token",
+            "https://mock.shopify.test/admin/api/2026-04/graphql.json",
+        ]
+    );
+    assert!(mock
+        .requests()
+        .iter()
+        .all(|request| request.headers["x-audit"] == "yes"));
+}
+
+#[tokio::test]
+async fn pool_builds_clients_from_the_store_and_limits_each_shop() {
+    let mock = MockShopify::new();
+    mock.respond("shop {", json!({"shop": {"name": "Shop A"}}));
+    let store = Arc::new(MemoryTokenStore::new());
+    for shop in ["shop-a", "shop-b"] {
+        store
+            .save_token(
+                &format!("{shop}.myshopify.com"),
+                TokenData::never_expiring(format!("token-{shop}")),
+            )
+            .await
+            .unwrap();
+    }
+    let pool = ShopifyPool::new(
+        ShopifyConfig {
+            token_store: Some(store.clone()),
+            ..ShopifyConfig::default()
+        },
+        PoolOptions {
+            max_concurrent_per_shop: 1,
+            ..PoolOptions::default()
+        },
+    )
+    .unwrap()
+    .with_transport(Arc::new(mock.clone()));
+
+    let shop_a = pool.acquire("shop-a").await.unwrap();
+    let data: ShopQuery = shop_a.graphql(SHOP_QUERY, &json!({})).await.unwrap();
+    assert_eq!(data.shop.name, "Shop A");
+    let request = &mock.requests()[0];
+    assert_eq!(
+        request.url,
+        "https://shop-a.myshopify.com/admin/api/2026-04/graphql.json"
+    );
+    assert_eq!(request.headers["x-shopify-access-token"], "token-shop-a");
+
+    let busy = tokio::time::timeout(Duration::from_millis(20), pool.acquire("shop-a")).await;
+    assert!(busy.is_err());
+    pool.acquire("shop-b.myshopify.com").await.unwrap();
+    drop(shop_a);
+    pool.acquire("shop-a").await.unwrap();
+    assert_eq!(pool.len(), 2);
+
+    assert!(matches!(
+        pool.client("unknown").await,
+        Err(ShopifyAPIError::Authentication(_))
+    ));
+
+    assert!(pool
+        .handle_webhook("app/uninstalled", "shop-a.myshopify.com")
+        .await
+        .unwrap());
+    assert_eq!(pool.len(), 1);
+    assert!(store
+        .load_token("shop-a.myshopify.com")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(pool.client("shop-a").await.is_err());
+}
+
+/// Reads the token immediately but answers late, like a slow database.
+struct SlowLoadStore(MemoryTokenStore);
+
+impl TokenStore for SlowLoadStore {
+    fn save_token<'a>(
+        &'a self,
+        session_id: &'a str,
+        token: TokenData,
+    ) -> shopify_api::auth::TokenStoreFuture<'a> {
+        self.0.save_token(session_id, token)
+    }
+
+    fn load_token<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> shopify_api::auth::TokenStoreFuture<'a, Option<TokenData>> {
+        Box::pin(async move {
+            let token = self.0.load_token(session_id).await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            token
+        })
+    }
+
+    fn delete_token<'a>(&'a self, session_id: &'a str) -> shopify_api::auth::TokenStoreFuture<'a> {
+        self.0.delete_token(session_id)
+    }
+}
+
+#[tokio::test]
+async fn pool_invalidation_beats_builds_in_flight_and_keeps_shop_limits() {
+    let store = Arc::new(SlowLoadStore(MemoryTokenStore::new()));
+    for shop in ["shop-a", "shop-b"] {
+        store
+            .save_token(
+                &format!("{shop}.myshopify.com"),
+                TokenData::never_expiring("token"),
+            )
+            .await
+            .unwrap();
+    }
+    let pool = ShopifyPool::new(
+        ShopifyConfig {
+            token_store: Some(store.clone()),
+            ..ShopifyConfig::default()
+        },
+        PoolOptions {
+            max_concurrent_per_shop: 1,
+            ..PoolOptions::default()
+        },
+    )
+    .unwrap()
+    .with_transport(Arc::new(MockShopify::new()));
+
+    // The uninstall lands while the client is built from the old token.
+    let building = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.client("shop-a").await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    pool.handle_webhook("app/uninstalled", "shop-a")
+        .await
+        .unwrap();
+    assert!(building.await.unwrap().is_err());
+    assert!(pool.is_empty());
+
+    // Invalidating a shop with a request in flight keeps its limit.
+    let leased = pool.acquire("shop-b").await.unwrap();
+    assert!(pool.invalidate("shop-b"));
+    let busy = tokio::time::timeout(Duration::from_millis(100), pool.acquire("shop-b")).await;
+    assert!(busy.is_err());
+    drop(leased);
+    pool.acquire("shop-b").await.unwrap();
+}
+
+#[tokio::test]
+async fn pool_evicts_idle_clients() {
+    let store = Arc::new(MemoryTokenStore::new());
+    store
+        .save_token("shop-a.myshopify.com", TokenData::never_expiring("token"))
+        .await
+        .unwrap();
+    let pool = ShopifyPool::new(
+        ShopifyConfig {
+            token_store: Some(store),
+            ..ShopifyConfig::default()
+        },
+        PoolOptions {
+            idle_timeout: Duration::ZERO,
+            ..PoolOptions::default()
+        },
+    )
+    .unwrap()
+    .with_transport(Arc::new(MockShopify::new()));
+
+    let leased = pool.acquire("shop-a").await.unwrap();
+    assert_eq!(pool.evict_idle(), 0);
+    drop(leased);
+    assert_eq!(pool.evict_idle(), 1);
+    assert!(pool.is_empty());
+}
+
+#[cfg(feature = "oauth")]
+#[tokio::test]
+async fn oauth_code_exchange_uses_the_base_url() {
+    use shopify_api::{
+        oauth::{AuthorizationCallback, ShopifyOAuth},
+        testing::MOCK_BASE_URL,
+    };
+
+    let mock = MockShopify::new();
+    let oauth = ShopifyOAuth::new(
+        "client-id",
+        "secret",
+        ["read_products"],
+        "https://app.test/auth/callback",
+    )
+    .with_base_url(MOCK_BASE_URL)
+    .with_transport(Arc::new(mock.clone()));
+
+    let token = oauth
+        .exchange_code(&AuthorizationCallback {
+            shop: "my-shop.myshopify.com".to_string(),
+            code: "code".to_string(),
+            host: None,
+            timestamp: Utc::now(),
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(token.access_token, "mock-token-1");
+    assert_eq!(
+        mock.requests()[0].url,
+        format!("{MOCK_BASE_URL}/admin/oauth/access_token")
+    );
+}
+
+#[cfg(feature = "storefront")]
+#[tokio::test]
+async fn storefront_client_sends_storefront_tokens_and_buyer_ip() {
+    use shopify_api::{
+        utils::ReadJsonTreeSteps, ShopifyStorefront, StorefrontAuth, StorefrontConfig,
+    };
+
+    let mock = MockShopify::new();
+    mock.respond("shop {", json!({"shop": {"name": "Public"}}));
+    mock.respond("shop {", json!({"shop": {"name": "Private"}}));
+    let storefront = |auth| {
+        ShopifyStorefront::new("my-shop", auth, StorefrontConfig::default())
+            .unwrap()
+            .with_transport(Arc::new(mock.clone()))
+    };
+
+    let public: ShopQuery = storefront(StorefrontAuth::Public("public-token".to_string()))
+        .graphql(SHOP_QUERY, &json!({}))
+        .await
+        .unwrap();
+    assert_eq!(public.shop.name, "Public");
+
+    let name: String = storefront(StorefrontAuth::Private("private-token".to_string()))
+        .with_buyer_ip("203.0.113.7")
+        .unwrap()
+        .graphql_at_path(
+            SHOP_QUERY,
+            &json!({}),
+            &[
+                ReadJsonTreeSteps::Key("shop"),
+                ReadJsonTreeSteps::Key("name"),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(name, "Private");
+
+    let requests = mock.requests();
+    assert_eq!(
+        requests[0].url,
+        "https://my-shop.myshopify.com/api/2026-04/graphql.json"
+    );
+    assert_eq!(
+        requests[0].headers["x-shopify-storefront-access-token"],
+        "public-token"
+    );
+    assert_eq!(
+        requests[1].headers["shopify-storefront-private-token"],
+        "private-token"
+    );
+    assert_eq!(
+        requests[1].headers["shopify-storefront-buyer-ip"],
+        "203.0.113.7"
+    );
+    assert!(!requests[1].headers.contains_key("x-shopify-access-token"));
+}
+
+#[cfg(feature = "tracing")]
+mod tracing_spans {
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    use super::*;
+
+    type Spans = Arc<Mutex<Vec<(String, Vec<(String, String)>)>>>;
+
+    /// Records span fields. Single-threaded tests only: the entered spans are
+    /// one stack.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        spans: Spans,
+        metadata: Arc<Mutex<Vec<&'static Metadata<'static>>>>,
+        entered: Arc<Mutex<Vec<span::Id>>>,
+    }
+
+    struct Fields<'a>(&'a mut Vec<(String, String)>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .push((field.name().to_string(), format!("{value:?}")));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+            let mut fields = Vec::new();
+            attrs.record(&mut Fields(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((attrs.metadata().name().to_string(), fields));
+            self.metadata.lock().unwrap().push(attrs.metadata());
+            span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            let fields = &mut spans[id.into_u64() as usize - 1].1;
+            values.record(&mut Fields(fields));
+        }
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, id: &span::Id) {
+            self.entered.lock().unwrap().push(id.clone());
+        }
+
+        fn exit(&self, _: &span::Id) {
+            self.entered.lock().unwrap().pop();
+        }
+
+        fn current_span(&self) -> tracing_core::span::Current {
+            match self.entered.lock().unwrap().last() {
+                Some(id) => {
+                    let metadata = self.metadata.lock().unwrap()[id.into_u64() as usize - 1];
+                    tracing_core::span::Current::new(id.clone(), metadata)
+                }
+                None => tracing_core::span::Current::none(),
+            }
+        }
+    }
+
+    fn field<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        fields
+            .iter()
+            .rev()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[tokio::test]
+    async fn spans_record_operation_details_without_secrets() {
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+        let mock = MockShopify::new();
+        mock.respond("shop {", json!({"shop": {"name": "Mock shop"}}));
+        let shopify = mock
+            .shopify(ShopifyAuth::client_credentials("client-id", "secret"))
+            .unwrap();
+
+        shopify
+            .graphql::<ShopQuery, _>("query ShopName { shop { name } }", &json!({}))
+            .await
+            .unwrap();
+        mock.fail_next(StatusCode::BAD_REQUEST, None);
+        shopify
+            .graphql::<ShopQuery, _>("query Failing { shop { name } }", &json!({}))
+            .await
+            .unwrap_err();
+
+        let spans = recorder.spans.lock().unwrap();
+        let (_, graphql) = spans
+            .iter()
+            .find(|(name, _)| name == "shopify.graphql")
+            .unwrap();
+        assert_eq!(field(graphql, "shop"), Some("\"my-shop.myshopify.com\""));
+        assert_eq!(field(graphql, "operation"), Some("\"ShopName\""));
+        assert_eq!(field(graphql, "http.status"), Some("200"));
+        assert_eq!(field(graphql, "cost"), Some("10.0"));
+        assert!(field(graphql, "latency_ms").is_some());
+        let (_, refresh) = spans
+            .iter()
+            .find(|(name, _)| name == "shopify.token.refresh")
+            .unwrap();
+        assert_eq!(field(refresh, "grant"), Some("\"client_credentials\""));
+        let (_, failing) = spans
+            .iter()
+            .find(|(name, fields)| {
+                name == "shopify.graphql" && field(fields, "operation") == Some("\"Failing\"")
+            })
+            .unwrap();
+        assert_eq!(field(failing, "http.status"), Some("400"));
+        assert_eq!(field(failing, "error"), Some("\"http\""));
+        assert_eq!(
+            field(failing, "http.request_id"),
+            Some("\"mock-request-id\"")
+        );
+
+        let recorded = format!("{spans:?}");
+        assert!(!recorded.contains("mock-token") && !recorded.contains("secret"));
+        assert!(!recorded.contains("Mock failure"));
+    }
+}